@@ -0,0 +1,170 @@
+use crate::{
+    perft::{self, Mode},
+    position::Position,
+    search::{Limits, SearchResult, Searcher, TranspositionTable},
+    tei::send,
+};
+use std::{
+    str::FromStr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// Positions from all phases of a game on the default board. Only the last has a forced win, a road
+/// in one.
+const POSITIONS: [&str; 6] = [
+    "x6/x6/x6/x6/x6/x6 1 1",
+    "x6/x4,1S,x/x2,21111S,1C,22122C,x/x6/x6/x6 2 11",
+    "x6/x2,2,1,x2/x,2,1,2,1,x/x,1,2,1,2,x/x2,1,2,x2/x6 1 7",
+    "2,2,x2,1,x/x,1,x,2,x2/x,2,1C,1,1,x/x,2,2C,1,2,x/x,1,x,2,1,x/x6 1 9",
+    "x,1,x,2,x2/1,2,12,1,2,x/x,21,2C,1S,1,x/2,1,1C,2,21,x/x,2,1,2,1,x/x2,2,x3 2 17",
+    "2,x,2,111S,2,12/2,122S,2122,1S,x,1/x,111,1,11S,x2/21122112C,x,212S,2S,2,1212S/1,112S,21221S,2S,x2/21,222,x,12S,x2 2 30",
+];
+
+/// Depth of the perft run on every benchmark position.
+const PERFT_DEPTH: i32 = 4;
+
+/// Searches every benchmark position to `depth`, first with one thread and then with `threads`,
+/// and reports how much sooner the parallel search reaches the depth. Each search starts from an
+/// empty transposition table of `hash_mb` megabytes.
+///
+/// Playing moves is the bulk of every search, so the speed of perft without the table is reported
+/// first, for each way of playing them.
+pub fn bench(depth: i32, threads: usize, hash_mb: usize, stop: &Arc<AtomicBool>) {
+    let tt = Arc::new(TranspositionTable::new(hash_mb));
+    for mode in [Mode::CopyMake, Mode::Unmake] {
+        let (time, nodes) = time_perft(PERFT_DEPTH, mode, stop, &tt);
+        if stop.load(Ordering::Relaxed) {
+            send!("bench: stopped");
+            return;
+        }
+        let mnps = nodes as f64 / time.as_secs_f64().max(1e-6) / 1_000_000.0;
+        send!("perft {mode:?}: {nodes} nodes {mnps:.1} Mnps {} ms", time.as_millis());
+    }
+
+    let mut totals = [(Duration::ZERO, 0); 2];
+
+    for (i, tps) in POSITIONS.iter().enumerate() {
+        let pos = Position::from_str(tps).unwrap();
+        let mut times = [Duration::ZERO; 2];
+        for (j, threads) in [1, threads].into_iter().enumerate() {
+            let (time, result) = time_to_depth(&pos, depth, threads, &tt, stop);
+            if stop.load(Ordering::Relaxed) {
+                send!("bench: stopped");
+                return;
+            }
+            send!(
+                "position {} threads {threads}: depth {} score {} bestmove {} {} nodes {} ms",
+                i + 1,
+                result.depth,
+                result.score(),
+                result.best_move,
+                result.nodes,
+                time.as_millis()
+            );
+            times[j] = time;
+            totals[j].0 += time;
+            totals[j].1 += result.nodes;
+        }
+        send!("position {}: speedup {:.2}", i + 1, speedup(times));
+    }
+
+    for (threads, (time, nodes)) in [1, threads].into_iter().zip(totals) {
+        let nps = (nodes as f64 / time.as_secs_f64().max(1e-6)) as u64;
+        send!("threads {threads}: {nodes} nodes {nps} nps {} ms", time.as_millis());
+    }
+    send!("speedup: {:.2}", speedup(totals.map(|(time, _)| time)));
+}
+
+/// Searches `pos` to `depth` with `threads` threads from an empty table, returning the time taken
+/// and the result.
+fn time_to_depth(
+    pos: &Position,
+    depth: i32,
+    threads: usize,
+    tt: &Arc<TranspositionTable>,
+    stop: &Arc<AtomicBool>,
+) -> (Duration, SearchResult) {
+    tt.clear();
+    let limits = Limits {
+        depth: Some(depth),
+        ..Limits::default()
+    };
+    let start = Instant::now();
+    let mut searcher = Searcher::new(limits, start, Arc::clone(stop), Arc::clone(tt));
+    searcher.set_threads(threads);
+    let result = searcher.search(pos).unwrap();
+    (start.elapsed(), result)
+}
+
+/// Runs perft to `depth` on every benchmark position, returning the time taken and the positions
+/// counted.
+fn time_perft(depth: i32, mode: Mode, stop: &AtomicBool, tt: &TranspositionTable) -> (Duration, u64) {
+    let start = Instant::now();
+    let nodes = POSITIONS
+        .iter()
+        .map(|tps| perft::count(&Position::from_str(tps).unwrap(), depth, mode, stop, tt))
+        .sum();
+    (start.elapsed(), nodes)
+}
+
+fn speedup([single, parallel]: [Duration; 2]) -> f64 {
+    single.as_secs_f64() / parallel.as_secs_f64().max(1e-6)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        position::MoveList,
+        search::{WIN, WIN_IN_MAX_PLY},
+    };
+
+    #[test]
+    fn positions() {
+        for tps in POSITIONS {
+            let pos = Position::from_str(tps).unwrap();
+            assert!(pos.terminal().is_none(), "{tps}");
+            assert_eq!(pos.size(), 6, "{tps}");
+        }
+    }
+
+    #[test]
+    fn parallel() {
+        let tt = Arc::new(TranspositionTable::new(1));
+        let stop = Arc::default();
+        // One thread and several must both reach the depth with a legal move, and agree on which
+        // positions have a forced result.
+        for (i, tps) in POSITIONS.iter().enumerate() {
+            let pos = Position::from_str(tps).unwrap();
+            let mut moves = MoveList::new();
+            pos.generate_moves(&mut moves);
+
+            for threads in [1, 4] {
+                let (_, result) = time_to_depth(&pos, 3, threads, &tt, &stop);
+                assert_eq!(result.depth, 3, "{tps}");
+                assert!(moves.contains(&result.best_move), "{tps}");
+                if i == POSITIONS.len() - 1 {
+                    assert_eq!(result.score(), WIN - 1, "{tps}");
+                    let result = pos.make_move(result.best_move).terminal().unwrap();
+                    assert_eq!(result.winner(), Some(pos.stm()), "{tps}");
+                } else {
+                    assert!(result.score().abs() < WIN_IN_MAX_PLY, "{tps}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn perft_modes() {
+        let tt = TranspositionTable::new(1);
+        let stop = AtomicBool::new(false);
+        let (_, copy_make) = time_perft(2, Mode::CopyMake, &stop, &tt);
+        let (_, unmake) = time_perft(2, Mode::Unmake, &stop, &tt);
+        assert!(copy_make > 0);
+        assert_eq!(copy_make, unmake);
+    }
+}
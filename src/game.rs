@@ -0,0 +1,211 @@
+use crate::{
+    position::{GameResult, MoveError, Position, Undo},
+    types::Move,
+};
+use std::collections::HashMap;
+
+/// A starting position together with the moves played from it.
+///
+/// Moves that are taken back with `undo` are kept until a different move is played, so that they
+/// can be replayed with `redo`. Repetition counts cover the starting position and every position
+/// reached along the current line.
+#[derive(Clone)]
+pub struct Game {
+    start: Position,
+    position: Position,
+    played: Vec<(Move, Undo)>,
+    redo: Vec<Move>,
+    repetitions: HashMap<u64, u32>,
+}
+
+impl Default for Game {
+    fn default() -> Game {
+        Game::new(Position::default())
+    }
+}
+
+impl Game {
+    #[must_use]
+    pub fn new(start: Position) -> Game {
+        let repetitions = HashMap::from([(start.key(), 1)]);
+        Game {
+            position: start.clone(),
+            start,
+            played: Vec::new(),
+            redo: Vec::new(),
+            repetitions,
+        }
+    }
+
+    #[must_use]
+    pub fn start(&self) -> &Position {
+        &self.start
+    }
+
+    #[must_use]
+    pub fn position(&self) -> &Position {
+        &self.position
+    }
+
+    pub fn moves(&self) -> impl Iterator<Item = Move> + '_ {
+        self.played.iter().map(|&(mv, _)| mv)
+    }
+
+    #[must_use]
+    pub fn result(&self) -> Option<GameResult> {
+        self.position.terminal()
+    }
+
+    pub fn set_half_komi(&mut self, half_komi: u8) {
+        self.start.set_half_komi(half_komi);
+        self.position.set_half_komi(half_komi);
+    }
+
+    /// Plays `mv` if it is legal and the game is not over yet.
+    pub fn play(&mut self, mv: Move) -> Result<(), MoveError> {
+        if self.result().is_some() {
+            return Err(MoveError::GameOver);
+        }
+        self.position
+            .check_move(mv)
+            .map_err(|err| MoveError::Illegal(mv.to_string(), err))?;
+
+        if self.redo.last() == Some(&mv) {
+            self.redo.pop();
+        } else {
+            self.redo.clear();
+        }
+
+        self.push(mv);
+        Ok(())
+    }
+
+    /// Parses and plays a PTN move.
+    pub fn play_str(&mut self, s: &str) -> Result<Move, MoveError> {
+        let mv = self.position.parse_move(s)?;
+        self.play(mv)?;
+        Ok(mv)
+    }
+
+    /// Takes back the last move, returning it.
+    pub fn undo(&mut self) -> Option<Move> {
+        let (mv, undo) = self.played.pop()?;
+
+        let count = self.repetitions.get_mut(&self.position.key()).unwrap();
+        *count -= 1;
+        if *count == 0 {
+            self.repetitions.remove(&self.position.key());
+        }
+
+        self.position.undo_move(mv, undo);
+        self.redo.push(mv);
+        Some(mv)
+    }
+
+    /// Replays the last move taken back with `undo`, returning it.
+    pub fn redo(&mut self) -> Option<Move> {
+        let mv = self.redo.pop()?;
+        self.push(mv);
+        Some(mv)
+    }
+
+    /// How many times the current position has occurred along the current line.
+    #[must_use]
+    pub fn repetitions(&self) -> u32 {
+        self.repetition_count(self.position.key())
+    }
+
+    /// How many times the position with Zobrist key `key` has occurred along the current line.
+    #[must_use]
+    pub fn repetition_count(&self, key: u64) -> u32 {
+        self.repetitions.get(&key).copied().unwrap_or(0)
+    }
+
+    fn push(&mut self, mv: Move) {
+        let undo = self.position.do_move(mv);
+        self.played.push((mv, undo));
+        *self.repetitions.entry(self.position.key()).or_insert(0) += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Color;
+    use std::str::FromStr;
+
+    fn play_all(game: &mut Game, moves: &[&str]) {
+        for mstr in moves {
+            game.play_str(mstr).unwrap();
+        }
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut game = Game::default();
+        play_all(&mut game, &["a1", "f6", "b1", "b2", "b1<"]);
+        assert_eq!(game.position().to_string(), "x5,1/x6/x6/x6/x,2,x4/21,x5 2 3");
+
+        assert_eq!(game.undo().unwrap().to_string(), "b1<");
+        assert_eq!(game.undo().unwrap().to_string(), "b2");
+        assert_eq!(game.position().to_string(), "x5,1/x6/x6/x6/x6/2,1,x4 2 2");
+        game.position().verify();
+
+        assert_eq!(game.redo().unwrap().to_string(), "b2");
+        assert_eq!(game.redo().unwrap().to_string(), "b1<");
+        assert!(game.redo().is_none());
+        assert_eq!(game.position().to_string(), "x5,1/x6/x6/x6/x,2,x4/21,x5 2 3");
+
+        while game.undo().is_some() {}
+        assert_eq!(game.position().to_string(), game.start().to_string());
+        assert_eq!(game.moves().count(), 0);
+    }
+
+    #[test]
+    fn new_move_clears_redo() {
+        let mut game = Game::default();
+        play_all(&mut game, &["a1", "f6", "b1"]);
+        game.undo();
+        game.play_str("c1").unwrap();
+        assert!(game.redo().is_none());
+        let moves: Vec<String> = game.moves().map(|mv| mv.to_string()).collect();
+        assert_eq!(moves, ["a1", "f6", "c1"]);
+    }
+
+    #[test]
+    fn repetitions() {
+        let start = Position::from_str("x6/x6/x6/x6/x6/1,2,x4 1 2").unwrap();
+        let mut game = Game::new(start);
+        assert_eq!(game.repetitions(), 1);
+
+        play_all(&mut game, &["c1", "d1", "c1<", "d1>"]);
+        let key = game.position().key();
+        play_all(&mut game, &["2b1>", "e1<", "2c1<", "d1>"]);
+        assert_eq!(game.repetitions(), 2);
+        assert_eq!(game.repetition_count(key), 2);
+
+        game.undo();
+        assert_eq!(game.repetition_count(key), 1);
+        game.redo();
+        assert_eq!(game.repetitions(), 2);
+    }
+
+    #[test]
+    fn result_and_errors() {
+        let start = Position::from_str("x5/x5/x5/1,1,1,1,x/2,2,2,2,x 1 5").unwrap();
+        let mut game = Game::new(start);
+
+        assert!(matches!(game.play_str("a2"), Err(MoveError::Illegal(..))));
+        assert!(game.result().is_none());
+
+        game.play_str("e2").unwrap();
+        let result = game.result().unwrap();
+        assert_eq!(result.winner(), Some(Color::P1));
+        assert_eq!(result.to_string(), "R-0");
+        assert!(matches!(game.play_str("e1"), Err(MoveError::GameOver)));
+
+        game.undo();
+        assert!(game.result().is_none());
+        game.play_str("e1").unwrap();
+    }
+}
@@ -2,15 +2,16 @@
 #![feature(portable_simd)]
 #![feature(uint_bit_width)]
 
+mod bench;
+mod game;
 mod perft;
 mod position;
+mod search;
 mod tei;
 mod types;
 
-pub const KOMI: u32 = 2;
-
 fn main() {
-    let mut interface = tei::Interface::default();
+    let mut interface = tei::Interface::new();
 
     let mut line = String::new();
     while let Ok(read_bytes) = std::io::stdin().read_line({
@@ -18,6 +19,8 @@ fn main() {
         &mut line
     }) && read_bytes > 0
     {
-        interface.parse_line(&line);
+        if interface.parse_line(&line).is_break() {
+            break;
+        }
     }
 }
@@ -1,11 +1,22 @@
-use crate::position::{MoveList, Position};
-use std::time::Instant;
+use crate::{
+    position::{MoveList, Position},
+    search::TranspositionTable,
+    tei::send,
+};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Instant,
+};
 
-fn core<const PRINT: bool>(pos: &Position, depth: i32) -> u64 {
+fn core<const PRINT: bool>(pos: &Position, depth: i32, stop: &AtomicBool) -> u64 {
     if depth <= 0 {
         return 1;
     }
 
+    if depth > 1 && stop.load(Ordering::Relaxed) {
+        return 0;
+    }
+
     if pos.terminal().is_some() {
         return 0;
     }
@@ -24,10 +35,10 @@ fn core<const PRINT: bool>(pos: &Position, depth: i32) -> u64 {
                 #[cfg(test)]
                 pos.verify();
 
-                let child = core::<false>(&pos, depth - 1);
+                let child = core::<false>(&pos, depth - 1, stop);
 
-                if PRINT {
-                    println!("{mv}\t: {child}");
+                if PRINT && !stop.load(Ordering::Relaxed) {
+                    send!("{mv}\t: {child}");
                 }
 
                 child
@@ -36,21 +47,206 @@ fn core<const PRINT: bool>(pos: &Position, depth: i32) -> u64 {
     }
 }
 
-pub fn splitperft(pos: &Position, depth: i32) {
+fn core_unmake<const PRINT: bool>(pos: &mut Position, depth: i32, stop: &AtomicBool) -> u64 {
+    if depth <= 0 {
+        return 1;
+    }
+
+    if depth > 1 && stop.load(Ordering::Relaxed) {
+        return 0;
+    }
+
+    if pos.terminal().is_some() {
+        return 0;
+    }
+
+    let mut moves = MoveList::new();
+    pos.generate_moves(&mut moves);
+
+    if depth == 1 && !PRINT {
+        moves.len() as u64
+    } else {
+        moves
+            .iter()
+            .map(|&mv| {
+                let undo = pos.do_move(mv);
+
+                #[cfg(test)]
+                pos.verify();
+
+                let child = core_unmake::<false>(pos, depth - 1, stop);
+
+                pos.undo_move(mv, undo);
+
+                if PRINT && !stop.load(Ordering::Relaxed) {
+                    send!("{mv}\t: {child}");
+                }
+
+                child
+            })
+            .sum()
+    }
+}
+
+/// Like `core_unmake`, but caches subtree counts in `tt`.
+fn core_hashed<const PRINT: bool>(pos: &mut Position, depth: i32, stop: &AtomicBool, tt: &TranspositionTable) -> u64 {
+    if depth <= 0 {
+        return 1;
+    }
+
+    if depth > 1 && stop.load(Ordering::Relaxed) {
+        return 0;
+    }
+
+    if pos.terminal().is_some() {
+        return 0;
+    }
+
+    if !PRINT
+        && depth > 1
+        && let Some(count) = tt.probe_count(pos.key(), depth)
+    {
+        return count;
+    }
+
+    let mut moves = MoveList::new();
+    pos.generate_moves(&mut moves);
+
+    if depth == 1 && !PRINT {
+        return moves.len() as u64;
+    }
+
+    let total = moves
+        .iter()
+        .map(|&mv| {
+            let undo = pos.do_move(mv);
+            let child = core_hashed::<false>(pos, depth - 1, stop, tt);
+            pos.undo_move(mv, undo);
+
+            if PRINT && !stop.load(Ordering::Relaxed) {
+                send!("{mv}\t: {child}");
+            }
+
+            child
+        })
+        .sum();
+
+    if !stop.load(Ordering::Relaxed) {
+        tt.store_count(pos.key(), depth, total);
+    }
+    total
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Mode {
+    /// Copies the position for every child with `make_move`.
+    #[default]
+    CopyMake,
+    /// Updates a single position in place with `do_move` and `undo_move`.
+    Unmake,
+    /// Like `Unmake`, but looks up and stores subtree counts in the transposition table.
+    Hashed,
+}
+
+/// Counts the positions `depth` plies after `pos`. If `stop` is raised part way through, the count
+/// reached so far is returned.
+pub fn count(pos: &Position, depth: i32, mode: Mode, stop: &AtomicBool, tt: &TranspositionTable) -> u64 {
+    match mode {
+        Mode::CopyMake => core::<false>(pos, depth, stop),
+        Mode::Unmake => core_unmake::<false>(&mut pos.clone(), depth, stop),
+        Mode::Hashed => core_hashed::<false>(&mut pos.clone(), depth, stop, tt),
+    }
+}
+
+/// Prints the node count below each root move and the total. If `stop` is raised part way through,
+/// the count reached so far is reported as a partial total.
+pub fn splitperft(pos: &Position, depth: i32, mode: Mode, stop: &AtomicBool, tt: &TranspositionTable) {
     let start = Instant::now();
-    let total = core::<true>(pos, depth);
+    let total = match mode {
+        Mode::CopyMake => core::<true>(pos, depth, stop),
+        Mode::Unmake => core_unmake::<true>(&mut pos.clone(), depth, stop),
+        Mode::Hashed => core_hashed::<true>(&mut pos.clone(), depth, stop, tt),
+    };
     let mnps = (total as f64 / start.elapsed().as_secs_f64()) / 1_000_000.0;
-    println!("total: {total}");
-    println!("{:.1} Mnps", mnps);
+    if stop.load(Ordering::Relaxed) {
+        send!("total: {total} (stopped)");
+    } else {
+        send!("total: {total}");
+    }
+    send!("{:.1} Mnps", mnps);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::Symmetry;
     use std::str::FromStr;
 
     fn perft(pos: &Position, depth: i32) -> u64 {
-        core::<false>(pos, depth)
+        core::<false>(pos, depth, &AtomicBool::new(false))
+    }
+
+    const CASES: [&str; 7] = [
+        "x6/x6/x6/x6/x6/x6 1 1",
+        "x,2,2,22S,2,111S/21S,22C,112,x,1112S,11S/x,2,112212,2,2S,2/x,2,121122,x,1112,211/21C,x,1,2S,21S,x/2S,x,212,1S,12S,1S 1 33",
+        "x2,2,22,2C,1/21221S,1112,x,2211,1,2/x2,111S,x,11S,12S/11S,1S,2S,2,12S,1211C/x,12S,2,122S,x,212S/12,x2,1S,22222S,21121 2 31",
+        "2,x,2,111S,2,12/2,122S,2122,1S,x,1/x,111,1,11S,x2/21122112C,x,212S,2S,2,1212S/1,112S,21221S,2S,x2/21,222,x,12S,x2 2 30",
+        "x6/x6/x6/x3,111222111222111222111222111222111222111222111222111222111222C,x2/x6/x6 2 31",
+        "x6/x4,1S,x/x2,21111S,1C,22122C,x/x6/x6/x6 2 11",
+        "x,2,1,2,1,2/2,1,2,1,2,1/1,2,1C,2,1,2/2,1,2,12C,2,1/1,2,1,2,1S,2/2,1,2,1,2,x 1 20",
+    ];
+
+    #[test]
+    fn unmake() {
+        for tps in CASES {
+            let pos = Position::from_str(tps).unwrap();
+            for depth in 0..4 {
+                let unmake = core_unmake::<false>(&mut pos.clone(), depth, &AtomicBool::new(false));
+                assert_eq!(perft(&pos, depth), unmake, "{tps}");
+            }
+        }
+    }
+
+    #[test]
+    fn hashed() {
+        // A small table, so that entries get replaced.
+        let tt = TranspositionTable::new(1);
+        let stop = AtomicBool::new(false);
+        for tps in CASES {
+            let pos = Position::from_str(tps).unwrap();
+            for depth in 0..4 {
+                assert_eq!(
+                    perft(&pos, depth),
+                    core_hashed::<false>(&mut pos.clone(), depth, &stop, &tt),
+                    "{tps}"
+                );
+            }
+        }
+
+        let mut pos = Position::default();
+        assert_eq!(core_hashed::<false>(&mut pos, 5, &stop, &tt), 1253506520);
+        let mut pos = Position::from_str("x6/x4,1S,x/x2,21111S,1C,22122C,x/x6/x6/x6 2 11").unwrap();
+        assert_eq!(core_hashed::<false>(&mut pos, 4, &stop, &tt), 111863932);
+    }
+
+    #[test]
+    fn symmetries() {
+        for tps in CASES {
+            let pos = Position::from_str(tps).unwrap();
+            let expected = perft(&pos, 3);
+            for sym in Symmetry::ALL {
+                assert_eq!(perft(&pos.transform(sym), 3), expected, "{tps} {sym:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn stopped() {
+        let pos = Position::default();
+        let stop = AtomicBool::new(true);
+        assert_eq!(core::<false>(&pos, 1, &stop), 36);
+        assert_eq!(core::<false>(&pos, 5, &stop), 0);
+        assert_eq!(core_unmake::<false>(&mut pos.clone(), 5, &stop), 0);
     }
 
     #[test]
@@ -62,6 +258,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn startpos_sizes() {
+        let cases: [(usize, &[u64]); 5] = [
+            (3, &[1, 9, 72, 1200, 17792, 271812, 3712952]),
+            (4, &[1, 16, 240, 7440, 216464, 6468872]),
+            (5, &[1, 25, 600, 43320, 2999784, 187855252]),
+            (7, &[1, 49, 2352, 339696, 48051008]),
+            (8, &[1, 64, 4032, 764064, 142512336]),
+        ];
+        for (size, cases) in cases {
+            let pos = Position::startpos(size);
+            for (depth, &case) in cases.iter().enumerate() {
+                assert_eq!(case, perft(&pos, depth as i32));
+            }
+        }
+    }
+
     #[test]
     fn complicated1() {
         let tps = "x,2,2,22S,2,111S/21S,22C,112,x,1112S,11S/x,2,112212,2,2S,2/x,2,121122,x,1112,211/21C,x,1,2S,21S,x/2S,x,212,1S,12S,1S 1 33";
@@ -102,6 +315,16 @@ mod tests {
         }
     }
 
+    #[test]
+    fn board_full() {
+        let tps = "x,2,1,2,1,2/2,1,2,1,2,1/1,2,1C,2,1,2/2,1,2,12C,2,1/1,2,1,2,1S,2/2,1,2,1,2,x 1 20";
+        let pos = Position::from_str(tps).unwrap();
+        let cases = [1, 56, 3546, 215520, 14674868];
+        for (depth, &case) in cases.iter().enumerate() {
+            assert_eq!(case, perft(&pos, depth as i32));
+        }
+    }
+
     #[test]
     fn capstone_blocking() {
         let tps = "x6/x4,1S,x/x2,21111S,1C,22122C,x/x6/x6/x6 2 11";
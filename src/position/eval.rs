@@ -0,0 +1,336 @@
+use super::Position;
+use crate::types::{Color, PieceType, Square};
+use std::fmt;
+
+/// Number of rings of squares around the centre of the largest board.
+pub const RINGS: usize = Position::MAX_SIZE.div_ceil(2);
+
+/// Weights of the evaluation terms, in hundredths of a flat. Tables indexed by ring count rings
+/// from the edge of the board inwards.
+#[derive(Clone, Debug)]
+pub struct EvalParams {
+    /// Per flat in the flat count, komi included.
+    pub flat: i32,
+    pub reserve_stone: i32,
+    pub reserve_cap: i32,
+    /// Per own piece buried in a stack, indexed by the type of the piece on top.
+    pub stack_reserve: [i32; PieceType::NUM],
+    /// Per opposing piece buried in a stack, indexed by the type of the piece on top.
+    pub captive: [i32; PieceType::NUM],
+    pub cap_centre: [i32; RINGS],
+    /// Per adjacent square a capstone can move onto.
+    pub cap_mobility: i32,
+    /// Per opposing road piece next to a wall.
+    pub wall_blocking: i32,
+    /// Per own road piece next to a wall.
+    pub wall_crowding: i32,
+    /// Per group of connected road pieces, indexed by how many ranks or files short of a road the
+    /// group spans in its longer direction.
+    pub road_gap: [i32; Position::MAX_SIZE],
+    pub flat_centre: [i32; RINGS],
+}
+
+impl EvalParams {
+    pub const DEFAULT: EvalParams = EvalParams {
+        flat: 100,
+        reserve_stone: 8,
+        reserve_cap: 30,
+        stack_reserve: [35, 40, 50],
+        captive: [10, 15, 20],
+        cap_centre: [0, 20, 35, 40],
+        cap_mobility: 8,
+        wall_blocking: 12,
+        wall_crowding: -6,
+        road_gap: [0, 60, 25, 10, 3, 0, 0, 0],
+        flat_centre: [0, 8, 14, 16],
+    };
+}
+
+impl Default for EvalParams {
+    fn default() -> EvalParams {
+        EvalParams::DEFAULT
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Term {
+    Flats,
+    Reserves,
+    Stacks,
+    Capstones,
+    Walls,
+    Roads,
+    Centre,
+}
+
+impl Term {
+    pub const NUM: usize = 7;
+    pub const ALL: [Term; Term::NUM] = [
+        Term::Flats,
+        Term::Reserves,
+        Term::Stacks,
+        Term::Capstones,
+        Term::Walls,
+        Term::Roads,
+        Term::Centre,
+    ];
+
+    #[must_use]
+    pub const fn to_index(self) -> usize {
+        self as usize
+    }
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Term::Flats => "flats",
+            Term::Reserves => "reserves",
+            Term::Stacks => "stacks",
+            Term::Capstones => "capstones",
+            Term::Walls => "walls",
+            Term::Roads => "roads",
+            Term::Centre => "centre",
+        }
+    }
+}
+
+/// An evaluation split into its terms, each scored separately for both players.
+#[derive(Clone, Debug)]
+pub struct Breakdown {
+    stm: Color,
+    scores: [[i32; Color::NUM]; Term::NUM],
+}
+
+impl Breakdown {
+    #[must_use]
+    pub fn score(&self, term: Term, c: Color) -> i32 {
+        self.scores[term.to_index()][c.to_index()]
+    }
+
+    /// The evaluation from the side to move's point of view.
+    #[must_use]
+    pub fn total(&self) -> i32 {
+        let [p1, p2] = self
+            .scores
+            .iter()
+            .fold([0, 0], |[p1, p2], scores| [p1 + scores[0], p2 + scores[1]]);
+        if self.stm == Color::P1 { p1 - p2 } else { p2 - p1 }
+    }
+
+    fn add(&mut self, term: Term, c: Color, score: i32) {
+        self.scores[term.to_index()][c.to_index()] += score;
+    }
+}
+
+/// Formats the breakdown as a table with a row per term, scored from P1's point of view.
+impl fmt::Display for Breakdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<10} {:>7} {:>7} {:>7}", "term", "p1", "p2", "diff")?;
+        for term in Term::ALL {
+            let [p1, p2] = [Color::P1, Color::P2].map(|c| self.score(term, c));
+            writeln!(f, "{:<10} {:>7} {:>7} {:>7}", term.name(), p1, p2, p1 - p2)?;
+        }
+        write!(f, "total: {} for player {} to move", self.total(), self.stm)
+    }
+}
+
+impl Position {
+    /// Static evaluation with the default weights, in hundredths of a flat from the side to
+    /// move's point of view. Does not check whether the game is over.
+    #[must_use]
+    pub fn evaluate(&self) -> i32 {
+        self.eval_breakdown(&EvalParams::DEFAULT).total()
+    }
+
+    #[must_use]
+    pub fn eval_breakdown(&self, params: &EvalParams) -> Breakdown {
+        let mut breakdown = Breakdown {
+            stm: self.stm,
+            scores: [[0; Color::NUM]; Term::NUM],
+        };
+        for c in [Color::P1, Color::P2] {
+            self.eval_material(c, params, &mut breakdown);
+            self.eval_stacks(c, params, &mut breakdown);
+            self.eval_caps(c, params, &mut breakdown);
+            self.eval_walls(c, params, &mut breakdown);
+            self.eval_roads(c, params, &mut breakdown);
+        }
+        breakdown
+    }
+
+    fn eval_material(&self, c: Color, params: &EvalParams, breakdown: &mut Breakdown) {
+        let mut flats = params.flat * self.flats(c).count_ones() as i32;
+        if c == Color::P2 {
+            flats += params.flat * self.half_komi as i32 / 2;
+        }
+        breakdown.add(Term::Flats, c, flats);
+
+        let reserves =
+            params.reserve_stone * self.remaining_stones(c) as i32 + params.reserve_cap * self.remaining_caps(c) as i32;
+        breakdown.add(Term::Reserves, c, reserves);
+
+        let centre: i32 = self.flats(c).map(|sq| params.flat_centre[self.ring(sq)]).sum();
+        breakdown.add(Term::Centre, c, centre);
+    }
+
+    /// Scores the pieces buried under stacks that `c` controls. A stack stores the colors of its
+    /// pieces from the top down, with a set bit for P2.
+    fn eval_stacks(&self, c: Color, params: &EvalParams, breakdown: &mut Breakdown) {
+        for sq in self.color(c) {
+            let i = sq.to_index();
+            let buried = self.heights[i] as u32 - 1;
+            if buried == 0 {
+                continue;
+            }
+
+            let p2 = (self.stacks[i] >> 1).count_ones();
+            let (own, captives) = match c {
+                Color::P1 => (buried - p2, p2),
+                Color::P2 => (p2, buried - p2),
+            };
+            let top = self.mailbox[i].piece_type().to_index();
+            let score = params.stack_reserve[top] * own as i32 + params.captive[top] * captives as i32;
+            breakdown.add(Term::Stacks, c, score);
+        }
+    }
+
+    fn eval_caps(&self, c: Color, params: &EvalParams, breakdown: &mut Breakdown) {
+        for sq in self.caps(c) {
+            let moves = sq.to_bitboard().adjacent() & self.board() & !self.all_caps();
+            let score = params.cap_centre[self.ring(sq)] + params.cap_mobility * moves.count_ones() as i32;
+            breakdown.add(Term::Capstones, c, score);
+        }
+    }
+
+    fn eval_walls(&self, c: Color, params: &EvalParams, breakdown: &mut Breakdown) {
+        for sq in self.all_walls() & self.color(c) {
+            let neighbours = sq.to_bitboard().adjacent();
+            let score = params.wall_blocking * (neighbours & self.roads(!c)).count_ones() as i32
+                + params.wall_crowding * (neighbours & self.roads(c)).count_ones() as i32;
+            breakdown.add(Term::Walls, c, score);
+        }
+    }
+
+    /// Scores each group of orthogonally connected road pieces by how far it reaches across the
+    /// board along ranks or files.
+    fn eval_roads(&self, c: Color, params: &EvalParams, breakdown: &mut Breakdown) {
+        let mut remaining = self.roads(c);
+        while remaining.is_some() {
+            let mut group = remaining.lsb().to_bitboard();
+            loop {
+                let next = (group | group.adjacent()) & remaining;
+                if next == group {
+                    break;
+                }
+                group = next;
+            }
+            remaining &= !group;
+
+            let ranks = group.0.to_le_bytes();
+            let files = ranks.iter().fold(0, |files, &rank| files | rank).count_ones();
+            let ranks = ranks.iter().filter(|&&rank| rank != 0).count() as u32;
+            let gap = self.size() - files.max(ranks) as usize;
+            breakdown.add(Term::Roads, c, params.road_gap[gap]);
+        }
+    }
+
+    /// How many squares `sq` lies from the nearest edge of the board.
+    fn ring(&self, sq: Square) -> usize {
+        let (file, rank) = sq.to_file_and_rank();
+        let edge = self.size() - 1;
+        file.min(rank).min(edge - file).min(edge - rank)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Symmetry;
+    use std::str::FromStr;
+
+    const CASES: [&str; 5] = [
+        "x6/2C,1,1,1,1,1/2,x,111121S,x3/2,x,11,x,1,x/2,1C,12,2,2,2/x,112,x4 2 22",
+        "x6/x4,1S,x/x2,21111S,1C,22122C,x/x6/x6/x6 2 11",
+        "2,x,2,111S,2,12/2,122S,2122,1S,x,1/x,111,1,11S,x2/21122112C,x,212S,2S,2,1212S/1,112S,21221S,2S,x2/21,222,x,12S,x2 2 30",
+        "x,1,2,x2/x,21C,12S,x,1/x5/2,x,12C,x2/x4,1 1 9",
+        "2,1,x/x,21S,x/1,x,2 2 4",
+    ];
+
+    fn eval(tps: &str) -> i32 {
+        Position::from_str(tps).unwrap().evaluate()
+    }
+
+    fn term(tps: &str, term: Term, c: Color) -> i32 {
+        let pos = Position::from_str(tps).unwrap();
+        pos.eval_breakdown(&EvalParams::DEFAULT).score(term, c)
+    }
+
+    #[test]
+    fn symmetric() {
+        for tps in CASES {
+            let pos = Position::from_str(tps).unwrap();
+            for sym in Symmetry::ALL {
+                assert_eq!(pos.transform(sym).evaluate(), pos.evaluate(), "{tps} {sym:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn side_to_move() {
+        for tps in CASES {
+            let other = match tps.split(' ').collect::<Vec<_>>()[..] {
+                [board, "1", fullmove] => format!("{board} 2 {fullmove}"),
+                [board, _, fullmove] => format!("{board} 1 {fullmove}"),
+                _ => unreachable!(),
+            };
+            assert_eq!(eval(tps), -eval(&other), "{tps}");
+        }
+    }
+
+    #[test]
+    fn breakdown() {
+        let pos = Position::from_str(CASES[0]).unwrap();
+        let breakdown = pos.eval_breakdown(&EvalParams::DEFAULT);
+        let diff: i32 = Term::ALL
+            .iter()
+            .map(|&term| breakdown.score(term, Color::P1) - breakdown.score(term, Color::P2))
+            .sum();
+        assert_eq!(breakdown.total(), -diff);
+        assert_eq!(breakdown.score(Term::Flats, Color::P1), 100 * 7);
+        assert_eq!(breakdown.score(Term::Flats, Color::P2), 100 * 8 + 200);
+        assert!(breakdown.to_string().ends_with("for player 2 to move"));
+
+        let mut params = EvalParams::DEFAULT;
+        params.flat = 0;
+        assert_eq!(pos.eval_breakdown(&params).score(Term::Flats, Color::P1), 0);
+    }
+
+    #[test]
+    fn terms() {
+        // Centre flats are worth more than edge flats.
+        assert!(eval("x6/x6/x6/x2,1,x3/x6/2,x5 1 2") > eval("x6/x6/x6/x6/x6/2,x4,1 1 2"));
+        assert!(
+            term("x6/x6/x6/x2,1,x3/x6/x6 2 1", Term::Centre, Color::P1)
+                > term("1,x5/x6/x6/x6/x6/x6 2 1", Term::Centre, Color::P1)
+        );
+
+        // Connected lines are worth more than scattered flats.
+        let line = "x6/x6/x6/1,1,1,1,x2/x6/x6 2 5";
+        let scattered = "1,x5/x6/x2,1,x3/x6/x4,1,x/x5,1 2 5";
+        assert!(term(line, Term::Roads, Color::P1) > term(scattered, Term::Roads, Color::P1));
+
+        // A capstone in the open is worth more than one in a corner.
+        let open = "x6/x6/x2,1C,x3/x6/x6/x6 2 1";
+        let corner = "x6/x6/x6/x6/x6/1C,x5 2 1";
+        assert!(term(open, Term::Capstones, Color::P1) > term(corner, Term::Capstones, Color::P1));
+
+        // Walls are better next to the opponent's road pieces than next to one's own.
+        assert!(term("x6/x6/x2,2,x3/x,2,1S,2,x2/x6/x6 2 3", Term::Walls, Color::P1) > 0);
+        assert!(term("x6/x6/x2,1,x3/x,1,1S,1,x2/x6/x6 2 3", Term::Walls, Color::P1) < 0);
+
+        // Own pieces under a stack are reserves, the opponent's are captives.
+        let stack = "x6/x6/x2,1121,x3/x6/x6/x6 2 5";
+        let score = term(stack, Term::Stacks, Color::P1);
+        assert_eq!(score, 2 * 35 + 10);
+    }
+}
@@ -0,0 +1,201 @@
+use super::Position;
+use crate::types::{Move, MoveParseError, PieceType};
+use thiserror::Error;
+
+#[derive(Error, Debug, Eq, PartialEq)]
+pub enum IllegalMove {
+    #[error("square is not on the board")]
+    OffBoard,
+    #[error("cannot place on an occupied square")]
+    Occupied,
+    #[error("only flats may be placed on the first turn")]
+    OpeningNotFlat,
+    #[error("cannot spread on the first turn")]
+    OpeningSpread,
+    #[error("no stones left in reserve")]
+    NoStones,
+    #[error("no capstones left in reserve")]
+    NoCaps,
+    #[error("cannot spread from a stack controlled by the opponent or from an empty square")]
+    NotOwned,
+    #[error("cannot lift more pieces than the stack height or carry limit")]
+    TooManyLifted,
+    #[error("spread leaves the board or runs into a wall or capstone")]
+    Blocked,
+}
+
+#[derive(Error, Debug)]
+pub enum MoveError {
+    #[error("invalid move string: {0}")]
+    Parse(#[from] MoveParseError),
+    #[error("illegal move `{0}`: {1}")]
+    Illegal(String, IllegalMove),
+    #[error("game is already over")]
+    GameOver,
+}
+
+impl Position {
+    #[must_use]
+    pub fn is_legal(&self, mv: Move) -> bool {
+        self.check_move(mv).is_ok()
+    }
+
+    pub fn check_move(&self, mv: Move) -> Result<(), IllegalMove> {
+        let sq = mv.sq();
+        if !self.board().get(sq) {
+            return Err(IllegalMove::OffBoard);
+        }
+
+        let stm = self.stm.to_index();
+
+        if mv.is_place() {
+            let pt = mv.piece_type();
+            if !self.is_empty(sq) {
+                return Err(IllegalMove::Occupied);
+            }
+            if self.ply < 2 {
+                return if pt == PieceType::Flat {
+                    Ok(())
+                } else {
+                    Err(IllegalMove::OpeningNotFlat)
+                };
+            }
+            return match pt {
+                PieceType::Cap if self.remaining_caps[stm] == 0 => Err(IllegalMove::NoCaps),
+                PieceType::Flat | PieceType::Wall if self.remaining_stones[stm] == 0 => Err(IllegalMove::NoStones),
+                _ => Ok(()),
+            };
+        }
+
+        if self.ply < 2 {
+            return Err(IllegalMove::OpeningSpread);
+        }
+
+        let top = self.piece_on(sq);
+        if top.is_none() || top.color() != self.stm {
+            return Err(IllegalMove::NotOwned);
+        }
+
+        let splat = mv.splat() as u32;
+        let lifted = splat.bit_width() as usize;
+        if lifted > (self.height(sq) as usize).min(self.carry_limit()) {
+            return Err(IllegalMove::TooManyLifted);
+        }
+
+        let d = mv.dir() as usize;
+        let limits = self.spread_calc(sq);
+        let steps = splat.count_ones() as usize;
+
+        if steps <= limits.distance[d] {
+            return Ok(());
+        }
+
+        // A capstone may flatten a wall by dropping exactly one piece onto it.
+        let blocker = limits.blocker[d];
+        let last_drop_is_single = lifted == 1 || splat & (1 << (lifted - 2)) != 0;
+        let can_crush = top.piece_type() == PieceType::Cap
+            && blocker.is_some()
+            && self.piece_on(blocker).piece_type() == PieceType::Wall
+            && steps == limits.distance[d] + 1
+            && last_drop_is_single;
+
+        if can_crush { Ok(()) } else { Err(IllegalMove::Blocked) }
+    }
+
+    /// Parses a PTN move and checks that it can be played in this position.
+    pub fn parse_move(&self, s: &str) -> Result<Move, MoveError> {
+        let mv: Move = s.parse()?;
+
+        if self.terminal().is_some() {
+            return Err(MoveError::GameOver);
+        }
+
+        self.check_move(mv)
+            .map_err(|err| MoveError::Illegal(s.to_string(), err))?;
+        Ok(mv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        position::MoveList,
+        types::{Dir, Square},
+    };
+    use std::{collections::HashSet, str::FromStr};
+
+    fn all_moves() -> Vec<Move> {
+        let mut moves = Vec::new();
+        for i in 0..Square::NUM {
+            let sq = Square::new(i as u8);
+            for pt in [PieceType::Flat, PieceType::Wall, PieceType::Cap] {
+                moves.push(Move::place(pt, sq));
+            }
+            for d in 0..Dir::NUM {
+                for splat in 1..=u8::MAX {
+                    moves.push(Move::spread(sq, Dir::from_index(d), splat));
+                }
+            }
+        }
+        moves
+    }
+
+    #[test]
+    fn matches_movegen() {
+        let cases = [
+            "x6/x6/x6/x6/x6/x6 1 1",
+            "x6/x6/x6/x6/x6/1,x5 2 1",
+            "x,2,2,22S,2,111S/21S,22C,112,x,1112S,11S/x,2,112212,2,2S,2/x,2,121122,x,1112,211/21C,x,1,2S,21S,x/2S,x,212,1S,12S,1S 1 33",
+            "x2,2,22,2C,1/21221S,1112,x,2211,1,2/x2,111S,x,11S,12S/11S,1S,2S,2,12S,1211C/x,12S,2,122S,x,212S/12,x2,1S,22222S,21121 2 31",
+            "2,x,2,111S,2,12/2,122S,2122,1S,x,1/x,111,1,11S,x2/21122112C,x,212S,2S,2,1212S/1,112S,21221S,2S,x2/21,222,x,12S,x2 2 30",
+            "x6/x6/x6/x3,111222111222111222111222111222111222111222111222111222111222C,x2/x6/x6 2 31",
+            "x6/x4,1S,x/x2,21111S,1C,22122C,x/x6/x6/x6 2 11",
+            "2,1,x/x,21S,x/1,x,2 2 4",
+            "x,1,2,x2/x,21C,12S,x,1/x5/2,x,12C,x2/x4,1 1 9",
+            "1,x7/x,2C,x6/x2,1C,x5/x8/x4,2121212121212121212121212121212121212121212121212121212121212121212121,x3/x8/x8/x7,2 2 40",
+        ];
+        let candidates = all_moves();
+
+        for tps in cases {
+            let pos = Position::from_str(tps).unwrap();
+            let mut ml = MoveList::new();
+            pos.generate_moves(&mut ml);
+            let generated: HashSet<Move> = ml.iter().copied().collect();
+
+            for &mv in &candidates {
+                assert_eq!(pos.is_legal(mv), generated.contains(&mv), "{tps} {mv}");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_move_errors() {
+        let pos = Position::from_str("x5/x5/x,2,x3/x,21C,1S,x2/x4,1 1 4").unwrap();
+        let cases = [
+            ("f1", IllegalMove::OffBoard),
+            ("b2", IllegalMove::Occupied),
+            ("Ca1", IllegalMove::NoCaps),
+            ("b3-", IllegalMove::NotOwned),
+            ("a1+", IllegalMove::NotOwned),
+            ("3b2+", IllegalMove::TooManyLifted),
+            ("2b2>11", IllegalMove::Blocked),
+            ("e1>", IllegalMove::Blocked),
+        ];
+        for (mstr, expected) in cases {
+            match pos.parse_move(mstr) {
+                Err(MoveError::Illegal(_, err)) => assert_eq!(err, expected, "{mstr}"),
+                _ => panic!("{mstr} should be illegal"),
+            }
+        }
+
+        assert!(pos.parse_move("b2>").is_ok());
+        assert!(pos.parse_move("2b2<").is_ok());
+        assert!(pos.parse_move("Sa1").is_ok());
+        assert!(matches!(pos.parse_move("a0"), Err(MoveError::Parse(_))));
+        assert!(matches!(
+            Position::default().parse_move("Sa1"),
+            Err(MoveError::Illegal(_, IllegalMove::OpeningNotFlat))
+        ));
+    }
+}
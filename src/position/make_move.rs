@@ -1,8 +1,14 @@
-use super::Position;
+use super::{Position, zobrist};
 use crate::types::{Color, Move, Piece, PieceType, Square};
 
-fn ones(count: u32) -> u64 {
-    debug_assert!(count < 64);
+#[derive(Copy, Clone, Debug)]
+pub struct Undo {
+    key: u64,
+    crushed: bool,
+}
+
+fn ones(count: u32) -> u128 {
+    debug_assert!(count < 128);
     (1 << count) - 1
 }
 
@@ -10,8 +16,18 @@ impl Position {
     #[must_use]
     pub fn make_move(&self, mv: Move) -> Position {
         let mut pos = self.clone();
+        pos.do_move(mv);
+        pos
+    }
+
+    /// Plays `mv` in place, returning what `undo_move` needs to take it back.
+    pub fn do_move(&mut self, mv: Move) -> Undo {
+        let mut undo = Undo {
+            key: self.key,
+            crushed: false,
+        };
 
-        let stm = if pos.ply < 2 { !pos.stm } else { pos.stm };
+        let stm = if self.ply < 2 { !self.stm } else { self.stm };
 
         let sq = mv.sq();
         let i = sq.to_index();
@@ -19,44 +35,51 @@ impl Position {
         if mv.is_place() {
             let pt = mv.piece_type();
 
-            debug_assert!(pos.heights[i] == 0);
-            debug_assert!(pos.mailbox[i].is_none());
+            debug_assert!(self.heights[i] == 0);
+            debug_assert!(self.mailbox[i].is_none());
 
-            pos.heights[i] = 1;
-            pos.stacks[i] = stm.to_index() as u64;
-            pos.mailbox[i] = Piece::new(stm, pt);
+            self.heights[i] = 1;
+            self.stacks[i] = stm.to_index() as u128;
+            self.mailbox[i] = Piece::new(stm, pt);
 
-            pos.colors[stm.to_index()].set(sq);
-            pos.tops[pt.to_index()].set(sq);
+            self.colors[stm.to_index()].set(sq);
+            self.tops[pt.to_index()].set(sq);
 
-            let remaining = match mv.piece_type() {
-                PieceType::Flat | PieceType::Wall => &mut pos.remaining_stones[stm.to_index()],
-                PieceType::Cap => &mut pos.remaining_caps[stm.to_index()],
+            self.key ^= zobrist::run_key(sq, self.stacks[i], 0, 1) ^ zobrist::top_key(sq, pt);
+
+            let (remaining, reserve_key): (_, fn(Color, u8) -> u64) = match mv.piece_type() {
+                PieceType::Flat | PieceType::Wall => (&mut self.remaining_stones[stm.to_index()], zobrist::stones_key),
+                PieceType::Cap => (&mut self.remaining_caps[stm.to_index()], zobrist::caps_key),
                 _ => unreachable!("Invalid piece to place"),
             };
+            self.key ^= reserve_key(stm, *remaining) ^ reserve_key(stm, *remaining - 1);
             *remaining -= 1;
         } else {
             debug_assert!(mv.splat() != 0);
 
-            let mut splat = mv.splat();
+            let mut splat = mv.splat() as u32;
             let mut hand_height = splat.bit_width();
-            let hand_colors = pos.stacks[i] & ones(hand_height);
-            let top = pos.mailbox[i];
+            let hand_colors = self.stacks[i] & ones(hand_height);
+            let top = self.mailbox[i];
+
+            let remaining_height = self.heights[i] as usize - hand_height as usize;
+            self.key ^= zobrist::run_key(sq, hand_colors, remaining_height, hand_height as usize);
+            self.key ^= zobrist::top_key(sq, top.piece_type());
 
-            pos.stacks[i] >>= hand_height;
-            pos.heights[i] -= hand_height as u8;
-            pos.mailbox[i] = if pos.heights[i] == 0 {
-                pos.colors[top.color().to_index()].unset(sq);
-                pos.tops[top.piece_type().to_index()].unset(sq);
+            self.stacks[i] >>= hand_height;
+            self.heights[i] -= hand_height as u8;
+            self.mailbox[i] = if self.heights[i] == 0 {
+                self.colors[top.color().to_index()].unset(sq);
+                self.tops[top.piece_type().to_index()].unset(sq);
 
                 Piece::None
             } else {
-                pos.colors[top.color().to_index()].unset(sq);
-                pos.tops[top.piece_type().to_index()].unset(sq);
+                self.colors[top.color().to_index()].unset(sq);
+                self.tops[top.piece_type().to_index()].unset(sq);
 
-                let color = Color::from_index((pos.stacks[i] & 1) as u8);
-                pos.colors[color.to_index()].set(sq);
-                pos.tops[PieceType::Flat.to_index()].set(sq);
+                let color = Color::from_index((self.stacks[i] & 1) as u8);
+                self.colors[color.to_index()].set(sq);
+                self.tops[PieceType::Flat.to_index()].set(sq);
 
                 Piece::new(color, PieceType::Flat)
             };
@@ -71,39 +94,147 @@ impl Position {
                 let drop_colors = (hand_colors >> (hand_height - drop_size)) & ones(drop_size);
                 let drop_top_color = Color::from_index((drop_colors & 1) as u8);
 
-                pos.heights[curr.to_index()] += drop_size as u8;
-                pos.stacks[curr.to_index()] <<= drop_size;
-                pos.stacks[curr.to_index()] |= drop_colors;
-                pos.mailbox[curr.to_index()] = Piece::new(drop_top_color, PieceType::Flat);
-                pos.colors[drop_top_color.to_index()].set(curr);
-                pos.colors[(!drop_top_color).to_index()].unset(curr);
-                pos.tops[PieceType::Flat.to_index()].set(curr);
+                undo.crushed = self.mailbox[curr.to_index()].piece_type() == PieceType::Wall;
+
+                let dest_height = self.heights[curr.to_index()] as usize;
+                self.key ^= zobrist::run_key(curr, drop_colors, dest_height, drop_size as usize);
+                self.key ^= zobrist::top_key(curr, self.mailbox[curr.to_index()].piece_type());
+
+                self.heights[curr.to_index()] += drop_size as u8;
+                self.stacks[curr.to_index()] <<= drop_size;
+                self.stacks[curr.to_index()] |= drop_colors;
+                self.mailbox[curr.to_index()] = Piece::new(drop_top_color, PieceType::Flat);
+                self.colors[drop_top_color.to_index()].set(curr);
+                self.colors[(!drop_top_color).to_index()].unset(curr);
+                self.tops[PieceType::Flat.to_index()].set(curr);
 
                 splat >>= drop_size;
                 hand_height -= drop_size;
             }
 
             if top.piece_type() != PieceType::Flat {
-                pos.tops[PieceType::Flat.to_index()].unset(curr);
-                pos.tops[PieceType::Wall.to_index()].unset(curr);
-                pos.tops[PieceType::Cap.to_index()].unset(curr);
+                self.tops[PieceType::Flat.to_index()].unset(curr);
+                self.tops[PieceType::Wall.to_index()].unset(curr);
+                self.tops[PieceType::Cap.to_index()].unset(curr);
 
-                pos.tops[top.piece_type().to_index()].set(curr);
+                self.tops[top.piece_type().to_index()].set(curr);
 
-                pos.mailbox[curr.to_index()] = top;
+                self.mailbox[curr.to_index()] = top;
+                self.key ^= zobrist::top_key(curr, top.piece_type());
             }
         }
 
-        pos.stm = !pos.stm;
-        pos.ply += 1;
+        self.key ^= zobrist::stm_key();
+        self.stm = !self.stm;
+        self.ply += 1;
 
-        pos
+        undo
+    }
+
+    /// Passes the turn. Not a legal move in Tak, but it lets analysis ask what the side that just
+    /// moved is threatening. Taken back by `undo_null_move`.
+    pub fn do_null_move(&mut self) {
+        self.key ^= zobrist::stm_key();
+        self.stm = !self.stm;
+        self.ply += 1;
+    }
+
+    pub fn undo_null_move(&mut self) {
+        self.key ^= zobrist::stm_key();
+        self.stm = !self.stm;
+        self.ply -= 1;
+    }
+
+    /// Takes back `mv`, which must be the last move played with `do_move`.
+    pub fn undo_move(&mut self, mv: Move, undo: Undo) {
+        self.stm = !self.stm;
+        self.ply -= 1;
+        self.key = undo.key;
+
+        let sq = mv.sq();
+        let i = sq.to_index();
+
+        if mv.is_place() {
+            let stm = if self.ply < 2 { !self.stm } else { self.stm };
+            let pt = mv.piece_type();
+
+            self.heights[i] = 0;
+            self.stacks[i] = 0;
+            self.set_top(sq, Piece::None);
+
+            match pt {
+                PieceType::Flat | PieceType::Wall => self.remaining_stones[stm.to_index()] += 1,
+                PieceType::Cap => self.remaining_caps[stm.to_index()] += 1,
+                _ => unreachable!("Invalid piece to place"),
+            }
+        } else {
+            let mut splat = mv.splat() as u32;
+            let hand_height = splat.bit_width();
+            let mut remaining = hand_height;
+            let mut hand_colors = 0;
+
+            let dir = mv.dir();
+            let mut curr = sq;
+            let mut top = PieceType::Flat;
+
+            while splat != 0 {
+                curr = curr.step(dir);
+
+                let j = curr.to_index();
+                let drop_size = splat.trailing_zeros() + 1;
+                let drop_colors = self.stacks[j] & ones(drop_size);
+
+                remaining -= drop_size;
+                hand_colors |= drop_colors << remaining;
+
+                top = self.mailbox[j].piece_type();
+                self.stacks[j] >>= drop_size;
+                self.heights[j] -= drop_size as u8;
+                splat >>= drop_size;
+
+                let piece = if self.heights[j] == 0 {
+                    Piece::None
+                } else {
+                    let color = Color::from_index((self.stacks[j] & 1) as u8);
+                    let pt = if splat == 0 && undo.crushed {
+                        PieceType::Wall
+                    } else {
+                        PieceType::Flat
+                    };
+                    Piece::new(color, pt)
+                };
+                self.set_top(curr, piece);
+            }
+
+            self.stacks[i] = (self.stacks[i] << hand_height) | hand_colors;
+            self.heights[i] += hand_height as u8;
+            self.set_top(sq, Piece::new(Color::from_index((hand_colors & 1) as u8), top));
+        }
+    }
+
+    fn set_top(&mut self, sq: Square, piece: Piece) {
+        let i = sq.to_index();
+
+        if self.mailbox[i].is_some() {
+            self.colors[self.mailbox[i].color().to_index()].unset(sq);
+            self.tops[self.mailbox[i].piece_type().to_index()].unset(sq);
+        }
+
+        if piece.is_some() {
+            self.colors[piece.color().to_index()].set(sq);
+            self.tops[piece.piece_type().to_index()].set(sq);
+        }
+
+        self.mailbox[i] = piece;
     }
 
     pub fn verify(&self) {
         for i in 0..Square::NUM {
             let sq = Square::new(i as u8);
             let height = self.heights[i];
+            if !self.board().get(sq) {
+                assert_eq!(height, 0);
+            }
             if height == 0 {
                 assert_eq!(self.stacks[i], 0);
                 assert_eq!(self.mailbox[i], Piece::None);
@@ -141,32 +272,38 @@ impl Position {
             }
         }
 
+        let starting_stones = Position::starting_stones(self.size());
+        let starting_caps = Position::starting_caps(self.size());
+
         let board_count: u8 = self.heights.iter().sum();
         let p2_count: u8 = self.stacks.iter().map(|x| x.count_ones() as u8).sum();
         let p1_count = board_count - p2_count;
 
         assert_eq!(
             p1_count + self.remaining_stones[0] + self.remaining_caps[0],
-            Position::STARTING_STONES + Position::STARTING_CAPS
+            starting_stones + starting_caps
         );
         assert_eq!(
             p2_count + self.remaining_stones[1] + self.remaining_caps[1],
-            Position::STARTING_STONES + Position::STARTING_CAPS
+            starting_stones + starting_caps
         );
         assert_eq!(
             self.caps(Color::P1).count_ones() as u8 + self.remaining_caps[0],
-            Position::STARTING_CAPS
+            starting_caps
         );
         assert_eq!(
             self.caps(Color::P2).count_ones() as u8 + self.remaining_caps[1],
-            Position::STARTING_CAPS
+            starting_caps
         );
+
+        assert_eq!(self.key, self.compute_key());
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::position::MoveList;
     use std::str::FromStr;
 
     #[test]
@@ -192,4 +329,51 @@ mod tests {
         assert_eq!(expected, position.to_string());
         position.verify();
     }
+
+    fn check_unmake(pos: &mut Position, depth: i32) {
+        if depth == 0 || pos.terminal().is_some() {
+            return;
+        }
+
+        let tps = pos.to_string();
+        let key = pos.key();
+
+        let mut moves = MoveList::new();
+        pos.generate_moves(&mut moves);
+
+        for &mv in moves.iter() {
+            let expected = pos.make_move(mv);
+
+            let undo = pos.do_move(mv);
+            pos.verify();
+            assert_eq!(expected.to_string(), pos.to_string(), "{tps} {mv}");
+            assert_eq!(expected.key(), pos.key(), "{tps} {mv}");
+            assert!(expected == *pos);
+
+            check_unmake(pos, depth - 1);
+
+            pos.undo_move(mv, undo);
+            pos.verify();
+            assert_eq!(tps, pos.to_string(), "{mv}");
+            assert_eq!(key, pos.key(), "{tps} {mv}");
+        }
+    }
+
+    #[test]
+    fn unmake_matches_copy_make() {
+        let cases = [
+            "x6/x6/x6/x6/x6/x6 1 1",
+            "x6/2C,1,1,1,1,1/2,x,111121S,x3/2,x,11,x,1,x/2,1C,12,2,2,2/x,112,x4 2 22",
+            "x6/x4,1S,x/x2,21111S,1C,22122C,x/x6/x6/x6 2 11",
+            "x6/x6/x6/x3,111222111222111222111222111222111222111222111222111222111222C,x2/x6/x6 2 31",
+            "2,x,2,111S,2,12/2,122S,2122,1S,x,1/x,111,1,11S,x2/21122112C,x,212S,2S,2,1212S/1,112S,21221S,2S,x2/21,222,x,12S,x2 2 30",
+            "x,1,2,x2/x,21C,12S,x,1/x5/2,x,12C,x2/x4,1 1 9",
+            "1,x7/x,2C,x6/x2,1C,x5/x8/x4,2121212121212121212121212121212121212121212121212121212121212121212121,x3/x8/x8/x7,2 2 40",
+        ];
+        for tps in cases {
+            let mut pos = Position::from_str(tps).unwrap();
+            check_unmake(&mut pos, 2);
+            assert_eq!(tps, pos.to_string());
+        }
+    }
 }
@@ -1,28 +1,58 @@
 use crate::types::{Bitboard, Color, Piece, PieceType, Square};
 
+mod eval;
+mod legal;
 mod make_move;
 mod movegen;
+mod nnue;
+mod result;
 mod road;
+mod symmetry;
 mod tps;
+mod zobrist;
 
+pub use eval::EvalParams;
+pub use legal::MoveError;
+pub use make_move::Undo;
 pub use movegen::MoveList;
+pub use nnue::{Network, NetworkError, Nnue};
+pub use result::{EndReason, GameResult};
 
 #[derive(Clone)]
 pub struct Position {
+    size: u8,
+    half_komi: u8,
     stm: Color,
     ply: u16,
     colors: [Bitboard; Color::NUM],
     tops: [Bitboard; PieceType::NUM],
     mailbox: [Piece; Square::NUM],
-    stacks: [u64; Square::NUM],
+    stacks: [u128; Square::NUM],
     heights: [u8; Square::NUM],
     remaining_stones: [u8; Color::NUM],
     remaining_caps: [u8; Color::NUM],
+    key: u64,
 }
 
 impl Default for Position {
     fn default() -> Position {
-        Position {
+        Position::startpos(Position::DEFAULT_SIZE)
+    }
+}
+
+impl Position {
+    pub const MIN_SIZE: usize = 3;
+    pub const MAX_SIZE: usize = 8;
+    pub const DEFAULT_SIZE: usize = 6;
+    pub const DEFAULT_HALF_KOMI: u8 = 4;
+
+    #[must_use]
+    pub fn startpos(size: usize) -> Position {
+        assert!(Position::is_valid_size(size));
+
+        let mut pos = Position {
+            size: size as u8,
+            half_komi: Position::DEFAULT_HALF_KOMI,
             stm: Color::P1,
             ply: 0,
             colors: Default::default(),
@@ -30,15 +60,79 @@ impl Default for Position {
             mailbox: [Piece::None; Square::NUM],
             stacks: [0; Square::NUM],
             heights: [0; Square::NUM],
-            remaining_stones: [Position::STARTING_STONES; Color::NUM],
-            remaining_caps: [Position::STARTING_CAPS; Color::NUM],
+            remaining_stones: [Position::starting_stones(size); Color::NUM],
+            remaining_caps: [Position::starting_caps(size); Color::NUM],
+            key: 0,
+        };
+        pos.key = pos.compute_key();
+        pos
+    }
+
+    #[must_use]
+    pub const fn is_valid_size(size: usize) -> bool {
+        size >= Position::MIN_SIZE && size <= Position::MAX_SIZE
+    }
+
+    #[must_use]
+    pub const fn starting_stones(size: usize) -> u8 {
+        match size {
+            3 => 10,
+            4 => 15,
+            5 => 21,
+            6 => 30,
+            7 => 40,
+            8 => 50,
+            _ => panic!("unsupported board size"),
         }
     }
-}
 
-impl Position {
-    pub const STARTING_STONES: u8 = 30;
-    pub const STARTING_CAPS: u8 = 1;
+    #[must_use]
+    pub const fn starting_caps(size: usize) -> u8 {
+        match size {
+            3 | 4 => 0,
+            5 | 6 => 1,
+            7 | 8 => 2,
+            _ => panic!("unsupported board size"),
+        }
+    }
+
+    #[must_use]
+    pub fn size(&self) -> usize {
+        self.size as usize
+    }
+
+    #[must_use]
+    pub fn stm(&self) -> Color {
+        self.stm
+    }
+
+    /// Plies played since the start of the game. The first two are the opening placements of the
+    /// opponent's flats.
+    #[must_use]
+    pub fn ply(&self) -> usize {
+        self.ply as usize
+    }
+
+    /// Komi awarded to P2 in a flat count, in units of half a flat.
+    #[must_use]
+    pub fn half_komi(&self) -> u8 {
+        self.half_komi
+    }
+
+    pub fn set_half_komi(&mut self, half_komi: u8) {
+        self.half_komi = half_komi;
+    }
+
+    /// The maximum number of pieces that may be lifted from a stack in one spread.
+    #[must_use]
+    pub fn carry_limit(&self) -> usize {
+        self.size()
+    }
+
+    #[must_use]
+    pub fn board(&self) -> Bitboard {
+        Bitboard::board_mask(self.size())
+    }
 
     #[must_use]
     pub fn is_empty(&self, sq: Square) -> bool {
@@ -50,6 +144,11 @@ impl Position {
         self.colors[0] | self.colors[1]
     }
 
+    #[must_use]
+    pub fn empty(&self) -> Bitboard {
+        self.board() & !self.occupied()
+    }
+
     #[must_use]
     pub fn piece_on(&self, sq: Square) -> Piece {
         self.mailbox[sq.to_index()]
@@ -60,6 +159,16 @@ impl Position {
         self.heights[sq.to_index()]
     }
 
+    #[must_use]
+    pub fn remaining_stones(&self, c: Color) -> u8 {
+        self.remaining_stones[c.to_index()]
+    }
+
+    #[must_use]
+    pub fn remaining_caps(&self, c: Color) -> u8 {
+        self.remaining_caps[c.to_index()]
+    }
+
     #[must_use]
     pub fn color(&self, c: Color) -> Bitboard {
         self.colors[c.to_index()]
@@ -5,15 +5,20 @@ use smallvec::SmallVec;
 pub type MoveList = SmallVec<[Move; 256]>;
 
 #[derive(Debug)]
-struct Limits {
-    distance: [usize; 4],
-    blocker: [Square; 4],
+pub(super) struct Limits {
+    pub(super) distance: [usize; 4],
+    pub(super) blocker: [Square; 4],
 }
 
 impl Position {
     pub fn generate_moves(&self, ml: &mut MoveList) {
         ml.clear();
+        self.generate_placements(ml);
+        self.generate_spreads(ml);
+    }
 
+    /// Appends every legal placement to `ml`.
+    pub fn generate_placements(&self, ml: &mut MoveList) {
         if self.ply < 2 {
             self.append_placements(ml, PieceType::Flat);
             return;
@@ -26,11 +31,17 @@ impl Position {
         if self.remaining_caps[self.stm.to_index()] > 0 {
             self.append_placements(ml, PieceType::Cap);
         }
-        self.append_spreads(ml);
+    }
+
+    /// Appends every legal spread to `ml`.
+    pub fn generate_spreads(&self, ml: &mut MoveList) {
+        if self.ply >= 2 {
+            self.append_spreads(ml);
+        }
     }
 
     fn append_placements(&self, ml: &mut MoveList, pt: PieceType) {
-        for sq in !self.occupied() {
+        for sq in self.empty() {
             ml.push(Move::place(pt, sq));
         }
     }
@@ -48,7 +59,7 @@ impl Position {
                 let blocker_distance = limits.distance[d];
 
                 let max_spread_dist = height.min(blocker_distance);
-                let max_pickup = height.min(6);
+                let max_pickup = height.min(self.carry_limit());
 
                 let can_crush = is_cap
                     && blocker.is_some()
@@ -56,10 +67,10 @@ impl Position {
                     && max_pickup > blocker_distance;
 
                 if max_spread_dist != 0 {
-                    let splat_limit: u8 = 1 << max_pickup;
+                    let splat_limit: u16 = 1 << max_pickup;
                     for splat in 1..splat_limit {
                         if splat.count_ones() as usize <= max_spread_dist {
-                            ml.push(Move::spread(sq, dir, splat));
+                            ml.push(Move::spread(sq, dir, splat as u8));
                         }
                     }
                 }
@@ -76,7 +87,7 @@ impl Position {
         }
     }
 
-    fn spread_calc(&self, sq: Square) -> Limits {
+    pub(super) fn spread_calc(&self, sq: Square) -> Limits {
         let bit = sq.to_bitboard();
 
         let (file, rank) = sq.to_file_and_rank();
@@ -93,13 +104,15 @@ impl Position {
         let s_blocker = s_blocker.msb();
         let w_blocker = w_blocker.msb();
 
+        let edge = self.size() - 1;
+
         let n_rank = if n_blocker.is_none() {
-            5
+            edge
         } else {
             n_blocker.rank() - 1
         };
         let e_file = if e_blocker.is_none() {
-            5
+            edge
         } else {
             e_blocker.file() - 1
         };
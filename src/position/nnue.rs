@@ -0,0 +1,428 @@
+//! A small quantised neural network evaluation, NNUE style: a wide first layer whose output, the
+//! accumulator, is kept up to date as moves are played, followed by a clipped ReLU and a single
+//! output neuron.
+//!
+//! The accumulators live in `Nnue`, which a search keeps next to the position it plays moves on.
+//! Positions know nothing about the network, so searches with the handcrafted evaluation and perft
+//! do not pay for it.
+//!
+//! # Inputs
+//!
+//! Every input is 0 or 1, and the network sees the board from P1's point of view:
+//!
+//! - For each of the 64 squares, in the order of `Square::to_index`, `SQUARE_FEATURES` inputs:
+//!   six for the top piece (`Piece::to_index`: P1 flat, P2 flat, P1 wall, P2 wall, P1 cap, P2
+//!   cap), then two per layer for the colours of the `LAYERS` pieces right below the top, nearest
+//!   first, P1 before P2.
+//! - For each player, P1 first, 51 inputs for the stones left in reserve (0 to 50), then for each
+//!   player 3 inputs for the capstones left (0 to 2).
+//! - One input that is set when P2 is to move.
+//!
+//! # File format
+//!
+//! All numbers are little endian:
+//!
+//! | field          | type  | count                |
+//! |----------------|-------|----------------------|
+//! | magic `PKNN`   | bytes | 4                    |
+//! | version, 1     | u32   | 1                    |
+//! | inputs         | u32   | 1                    |
+//! | hidden size    | u32   | 1                    |
+//! | input weights  | i16   | inputs × hidden size |
+//! | hidden biases  | i16   | hidden size          |
+//! | output weights | i16   | hidden size          |
+//! | output bias    | i32   | 1                    |
+//!
+//! Input weights are stored input by input. Input weights and hidden biases are scaled by `QA`, so
+//! that `QA` stands for 1.0, output weights by `QB`, and the output bias by `QA * QB`. The output
+//! of the network is the evaluation for P1 in flats.
+
+use super::{Position, Undo};
+use crate::types::{Color, Move, Piece, PieceType, Square};
+use smallvec::SmallVec;
+use std::{fs, io, sync::Arc};
+use thiserror::Error;
+
+/// Number of pieces below the top of a stack whose colours are inputs.
+pub const LAYERS: usize = 3;
+const PIECE_FEATURES: usize = 6;
+const SQUARE_FEATURES: usize = PIECE_FEATURES + 2 * LAYERS;
+const STONE_BUCKETS: usize = Position::starting_stones(Position::MAX_SIZE) as usize + 1;
+const CAP_BUCKETS: usize = Position::starting_caps(Position::MAX_SIZE) as usize + 1;
+
+const STONES_BASE: usize = Square::NUM * SQUARE_FEATURES;
+const CAPS_BASE: usize = STONES_BASE + Color::NUM * STONE_BUCKETS;
+const STM_FEATURE: usize = CAPS_BASE + Color::NUM * CAP_BUCKETS;
+/// Number of inputs of the network.
+pub const FEATURES: usize = STM_FEATURE + 1;
+/// Size of the hidden layer.
+pub const HIDDEN: usize = 64;
+
+const QA: i32 = 255;
+const QB: i32 = 64;
+/// Evaluations are reported in hundredths of a flat.
+const SCALE: i32 = 100;
+
+const MAGIC: &[u8; 4] = b"PKNN";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 16;
+const FILE_SIZE: usize = HEADER_SIZE + (FEATURES * HIDDEN + 2 * HIDDEN) * 2 + 4;
+
+#[derive(Error, Debug)]
+pub enum NetworkError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("not a network file")]
+    BadMagic,
+    #[error("unsupported version {0}")]
+    UnsupportedVersion(u32),
+    #[error("network has {inputs} inputs and {hidden} hidden neurons, expected {FEATURES} and {HIDDEN}")]
+    WrongShape { inputs: u32, hidden: u32 },
+    #[error("file is {0} bytes long, expected {FILE_SIZE}")]
+    WrongSize(usize),
+}
+
+/// The weights of a network, quantised as described in the module documentation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Network {
+    input_weights: Box<[[i16; HIDDEN]]>,
+    hidden_biases: [i16; HIDDEN],
+    output_weights: [i16; HIDDEN],
+    output_bias: i32,
+}
+
+impl Network {
+    /// Reads a network from the file at `path`.
+    pub fn load(path: &str) -> Result<Network, NetworkError> {
+        Network::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Network, NetworkError> {
+        if bytes.len() < HEADER_SIZE || &bytes[..4] != MAGIC {
+            return Err(NetworkError::BadMagic);
+        }
+        let header = |i: usize| u32::from_le_bytes(bytes[4 * i..4 * i + 4].try_into().unwrap());
+        if header(1) != VERSION {
+            return Err(NetworkError::UnsupportedVersion(header(1)));
+        }
+        if (header(2), header(3)) != (FEATURES as u32, HIDDEN as u32) {
+            return Err(NetworkError::WrongShape {
+                inputs: header(2),
+                hidden: header(3),
+            });
+        }
+        if bytes.len() != FILE_SIZE {
+            return Err(NetworkError::WrongSize(bytes.len()));
+        }
+
+        let mut values = bytes[HEADER_SIZE..]
+            .chunks_exact(2)
+            .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]));
+        let mut row = || std::array::from_fn(|_| values.next().unwrap());
+        let input_weights = (0..FEATURES).map(|_| row()).collect();
+        let hidden_biases = row();
+        let output_weights = row();
+        let output_bias = i32::from_le_bytes(bytes[FILE_SIZE - 4..].try_into().unwrap());
+
+        Ok(Network {
+            input_weights,
+            hidden_biases,
+            output_weights,
+            output_bias,
+        })
+    }
+
+    #[cfg(test)]
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        for n in [VERSION, FEATURES as u32, HIDDEN as u32] {
+            bytes.extend(n.to_le_bytes());
+        }
+        let rows = self
+            .input_weights
+            .iter()
+            .chain([&self.hidden_biases, &self.output_weights]);
+        bytes.extend(rows.flatten().flat_map(|w| w.to_le_bytes()));
+        bytes.extend(self.output_bias.to_le_bytes());
+        bytes
+    }
+
+    /// The fallback network when no file is loaded. It is set by hand rather than trained, and
+    /// only counts flats on top of stacks and pieces in reserve, so it is far weaker than the
+    /// handcrafted evaluation.
+    #[must_use]
+    pub fn embedded() -> Network {
+        // Four hidden neurons count P1's flats, P2's flats, P1's reserves and P2's reserves. A flat
+        // adds 3 to its neuron and a stone in reserve 4, with capstones in reserve worth 3.75
+        // stones, so 15. This keeps the counts below `QA`.
+        let mut network = Network {
+            input_weights: vec![[0; HIDDEN]; FEATURES].into_boxed_slice(),
+            hidden_biases: [0; HIDDEN],
+            output_weights: [0; HIDDEN],
+            output_bias: 0,
+        };
+        for sq in 0..Square::NUM {
+            for c in [Color::P1, Color::P2] {
+                let flat = Piece::new(c, PieceType::Flat);
+                network.input_weights[sq * SQUARE_FEATURES + flat.to_index()][c.to_index()] = 3;
+            }
+        }
+        for c in 0..Color::NUM {
+            for stones in 0..STONE_BUCKETS {
+                network.input_weights[STONES_BASE + c * STONE_BUCKETS + stones][2 + c] = 4 * stones as i16;
+            }
+            for caps in 0..CAP_BUCKETS {
+                network.input_weights[CAPS_BASE + c * CAP_BUCKETS + caps][2 + c] = 15 * caps as i16;
+            }
+        }
+
+        // A flat is worth 1 and a stone in reserve 0.08, the same as in the handcrafted evaluation.
+        let weight = |flats: f64, count: f64| (flats * (QA * QB) as f64 / count).round() as i16;
+        let (flat, stone) = (weight(1.0, 3.0), weight(0.08, 4.0));
+        network.output_weights[..4].copy_from_slice(&[flat, -flat, stone, -stone]);
+        network
+    }
+
+    /// The network's evaluation of `pos` in hundredths of a flat from the side to move's point of
+    /// view, computed from scratch.
+    #[must_use]
+    pub fn evaluate(&self, pos: &Position) -> i32 {
+        self.output(&self.accumulate(pos), pos.stm())
+    }
+
+    fn accumulate(&self, pos: &Position) -> Accumulator {
+        let mut accumulator = Accumulator(self.hidden_biases);
+        pos.features(pos.occupied(), |feature| accumulator.add(&self.input_weights[feature]));
+        accumulator
+    }
+
+    /// The evaluation in hundredths of a flat from `stm`'s point of view.
+    fn output(&self, accumulator: &Accumulator, stm: Color) -> i32 {
+        let sum: i32 = accumulator
+            .0
+            .iter()
+            .zip(&self.output_weights)
+            .map(|(&value, &weight)| (value as i32).clamp(0, QA) * weight as i32)
+            .sum();
+        let p1 = (sum + self.output_bias) * SCALE / (QA * QB);
+        if stm == Color::P1 { p1 } else { -p1 }
+    }
+}
+
+/// Evaluates the positions of a search with a network. It keeps an accumulator for the position
+/// the search started from and one for every move played since, so that a move only updates the
+/// inputs of the stacks it changes.
+#[derive(Clone)]
+pub struct Nnue {
+    network: Arc<Network>,
+    stack: Vec<Accumulator>,
+}
+
+impl Nnue {
+    #[must_use]
+    pub fn new(network: Arc<Network>) -> Nnue {
+        Nnue {
+            network,
+            stack: Vec::new(),
+        }
+    }
+
+    /// Starts again from `pos`. Must be called before any other method.
+    pub fn refresh(&mut self, pos: &Position) {
+        self.stack.clear();
+        self.stack.push(self.network.accumulate(pos));
+    }
+
+    /// Forgets the moves played since the last refresh, for callers that go back to a copy of the
+    /// position they refreshed with rather than taking the moves back.
+    pub fn rewind(&mut self) {
+        self.stack.truncate(1);
+    }
+
+    /// Plays `mv` on `pos`, the position after the moves played since the last refresh.
+    pub fn do_move(&mut self, pos: &mut Position, mv: Move) -> Undo {
+        let squares = touched_squares(mv);
+        let weights = &self.network.input_weights;
+        let mut accumulator = self.current();
+        pos.features(squares.iter().copied(), |feature| accumulator.sub(&weights[feature]));
+        let undo = pos.do_move(mv);
+        pos.features(squares.iter().copied(), |feature| accumulator.add(&weights[feature]));
+        self.stack.push(accumulator);
+        undo
+    }
+
+    /// Takes back `mv`, which must be the last move played with `do_move`.
+    pub fn undo_move(&mut self, pos: &mut Position, mv: Move, undo: Undo) {
+        pos.undo_move(mv, undo);
+        self.stack.pop();
+    }
+
+    /// The evaluation of `pos`, the position after the moves played since the last refresh, in
+    /// hundredths of a flat from the side to move's point of view.
+    #[must_use]
+    pub fn evaluate(&self, pos: &Position) -> i32 {
+        self.network.output(&self.current(), pos.stm())
+    }
+
+    fn current(&self) -> Accumulator {
+        *self.stack.last().expect("the accumulators have not been refreshed")
+    }
+}
+
+/// The output of the first layer, before the activation, for one position.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Accumulator([i16; HIDDEN]);
+
+impl Accumulator {
+    fn add(&mut self, weights: &[i16; HIDDEN]) {
+        for (value, weight) in self.0.iter_mut().zip(weights) {
+            *value = value.wrapping_add(*weight);
+        }
+    }
+
+    fn sub(&mut self, weights: &[i16; HIDDEN]) {
+        for (value, weight) in self.0.iter_mut().zip(weights) {
+            *value = value.wrapping_sub(*weight);
+        }
+    }
+}
+
+/// The squares whose stacks `mv` changes.
+fn touched_squares(mv: Move) -> SmallVec<[Square; 9]> {
+    let mut squares = SmallVec::new();
+    squares.push(mv.sq());
+    if mv.is_spread() {
+        let mut sq = mv.sq();
+        for _ in 0..mv.splat().count_ones() {
+            sq = sq.step(mv.dir());
+            squares.push(sq);
+        }
+    }
+    squares
+}
+
+impl Position {
+    /// Calls `f` with every input that is set and depends on the stacks on `squares`, the reserves
+    /// or the side to move. Moves take these inputs out before they change anything and put them
+    /// back in afterwards.
+    fn features(&self, squares: impl IntoIterator<Item = Square>, mut f: impl FnMut(usize)) {
+        for sq in squares {
+            let i = sq.to_index();
+            if self.heights[i] == 0 {
+                continue;
+            }
+            let base = i * SQUARE_FEATURES;
+            f(base + self.mailbox[i].to_index());
+            for layer in 0..LAYERS.min(self.heights[i] as usize - 1) {
+                let color = (self.stacks[i] >> (layer + 1)) & 1;
+                f(base + PIECE_FEATURES + 2 * layer + color as usize);
+            }
+        }
+
+        for c in 0..Color::NUM {
+            f(STONES_BASE + c * STONE_BUCKETS + self.remaining_stones[c] as usize);
+            f(CAPS_BASE + c * CAP_BUCKETS + self.remaining_caps[c] as usize);
+        }
+        if self.stm == Color::P2 {
+            f(STM_FEATURE);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::MoveList;
+    use std::str::FromStr;
+
+    #[test]
+    fn embedded() {
+        let network = Network::embedded();
+        assert_eq!(network.evaluate(&Position::default()), 0);
+
+        // P1 is a flat up but a stone down in reserve.
+        let pos = Position::from_str("x5/x5/x5/1,1,x3/2,x4 2 3").unwrap();
+        assert_eq!(network.evaluate(&pos), -92);
+        assert_eq!(network.evaluate(&pos.make_move(Move::from_str("e5").unwrap())), 0);
+
+        // Walls and buried pieces do not count, only the flats on top: P1 is two flats and a stone
+        // down.
+        let pos = Position::from_str("x5/x5/x5/1S,112,x3/2,x4 1 5").unwrap();
+        assert!((network.evaluate(&pos) + 208).abs() <= 1);
+
+        // A capstone in reserve is worth 30, as in the handcrafted evaluation.
+        let pos = Position::from_str("x5/x5/x5/x5/1,2,1C,x2 2 3").unwrap();
+        assert!((network.evaluate(&pos) - 30).abs() <= 1);
+    }
+
+    /// Playing and taking back random moves keeps the accumulator equal to one computed from
+    /// scratch. The network has random weights so that every input counts.
+    #[test]
+    fn incremental() {
+        let mut seed = 0x9e37_79b9_7f4a_7c15_u64;
+        let mut random = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+
+        let mut network = Network::embedded();
+        for weight in network.input_weights.iter_mut().flatten() {
+            *weight = (random() % 64) as i16 - 32;
+        }
+        let network = Arc::new(network);
+        let mut nnue = Nnue::new(Arc::clone(&network));
+
+        for size in Position::MIN_SIZE..=Position::MAX_SIZE {
+            let mut pos = Position::startpos(size);
+            nnue.refresh(&pos);
+            let mut played = Vec::new();
+            while pos.terminal().is_none() {
+                let mut moves = MoveList::new();
+                pos.generate_moves(&mut moves);
+                let mv = moves[random() as usize % moves.len()];
+                played.push((mv, nnue.do_move(&mut pos, mv)));
+                assert_eq!(nnue.current(), network.accumulate(&pos), "{pos} after {mv}");
+                assert_eq!(nnue.evaluate(&pos), network.evaluate(&pos));
+            }
+            while let Some((mv, undo)) = played.pop() {
+                nnue.undo_move(&mut pos, mv, undo);
+                assert_eq!(nnue.current(), network.accumulate(&pos), "{pos} before {mv}");
+            }
+            assert_eq!(nnue.stack.len(), 1);
+        }
+    }
+
+    #[test]
+    fn file() {
+        let mut network = Network::embedded();
+        network.input_weights[17][5] = -300;
+        network.output_bias = 1234;
+        let bytes = network.to_bytes();
+        assert_eq!(bytes.len(), FILE_SIZE);
+        assert_eq!(Network::from_bytes(&bytes).unwrap(), network);
+
+        assert!(matches!(Network::from_bytes(b"ABCD"), Err(NetworkError::BadMagic)));
+        let mut wrong = bytes.clone();
+        wrong[4] = 2;
+        assert!(matches!(
+            Network::from_bytes(&wrong),
+            Err(NetworkError::UnsupportedVersion(2))
+        ));
+        let mut wrong = bytes.clone();
+        wrong[12] = 32;
+        assert!(matches!(
+            Network::from_bytes(&wrong),
+            Err(NetworkError::WrongShape { hidden: 32, .. })
+        ));
+        assert!(matches!(
+            Network::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(NetworkError::WrongSize(_))
+        ));
+        assert!(matches!(
+            Network::load("/nonexistent/pentakle.nnue"),
+            Err(NetworkError::Io(_))
+        ));
+    }
+}
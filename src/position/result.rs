@@ -0,0 +1,187 @@
+use super::Position;
+use crate::types::Color;
+use std::cmp::Ordering;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum EndReason {
+    Road,
+    BoardFull,
+    ReservesExhausted,
+    Resignation,
+    TimeForfeit,
+}
+
+#[allow(dead_code)]
+impl EndReason {
+    #[must_use]
+    pub fn is_flat_count(self) -> bool {
+        matches!(self, EndReason::BoardFull | EndReason::ReservesExhausted)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct GameResult {
+    winner: Option<Color>,
+    reason: EndReason,
+    flats: [u32; Color::NUM],
+}
+
+#[allow(dead_code)]
+impl GameResult {
+    #[must_use]
+    pub fn winner(self) -> Option<Color> {
+        self.winner
+    }
+
+    #[must_use]
+    pub fn is_draw(self) -> bool {
+        self.winner.is_none()
+    }
+
+    #[must_use]
+    pub fn reason(self) -> EndReason {
+        self.reason
+    }
+
+    /// Flats on top of stacks for `c` when the game ended, not including komi.
+    #[must_use]
+    pub fn flats(self, c: Color) -> u32 {
+        self.flats[c.to_index()]
+    }
+}
+
+/// Formats the result as a PTN result string, e.g. `R-0`, `0-F`, `1-0` or `1/2-1/2`.
+impl std::fmt::Display for GameResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let win = match self.reason {
+            EndReason::Road => "R",
+            EndReason::BoardFull | EndReason::ReservesExhausted => "F",
+            EndReason::Resignation | EndReason::TimeForfeit => "1",
+        };
+
+        match self.winner {
+            Some(Color::P1) => write!(f, "{win}-0"),
+            Some(Color::P2) => write!(f, "0-{win}"),
+            None => write!(f, "1/2-1/2"),
+        }
+    }
+}
+
+impl Position {
+    #[must_use]
+    pub fn flat_counts(&self) -> [u32; Color::NUM] {
+        [self.flats(Color::P1).count_ones(), self.flats(Color::P2).count_ones()]
+    }
+
+    /// Compares flat counts with komi applied, as used when the game ends without a road.
+    #[must_use]
+    pub fn flat_winner(&self) -> Option<Color> {
+        let [p1flats, p2flats] = self.flat_counts();
+        match (2 * p1flats).cmp(&(2 * p2flats + self.half_komi() as u32)) {
+            Ordering::Less => Some(Color::P2),
+            Ordering::Greater => Some(Color::P1),
+            Ordering::Equal => None,
+        }
+    }
+
+    #[must_use]
+    pub fn terminal(&self) -> Option<GameResult> {
+        // A spread can complete roads for both players at once, in which case the mover wins.
+        let mover = !self.stm;
+        let (winner, reason) = if self.road_completed(mover) {
+            (Some(mover), EndReason::Road)
+        } else if self.road_completed(!mover) {
+            (Some(!mover), EndReason::Road)
+        } else if self.empty().is_empty() {
+            (self.flat_winner(), EndReason::BoardFull)
+        } else if self
+            .remaining_stones
+            .iter()
+            .zip(self.remaining_caps.iter())
+            .any(|x| x == (&0, &0))
+        {
+            (self.flat_winner(), EndReason::ReservesExhausted)
+        } else {
+            return None;
+        };
+
+        Some(GameResult {
+            winner,
+            reason,
+            flats: self.flat_counts(),
+        })
+    }
+
+    /// The result of `loser` resigning in this position.
+    #[allow(dead_code)]
+    #[must_use]
+    pub fn resignation(&self, loser: Color) -> GameResult {
+        GameResult {
+            winner: Some(!loser),
+            reason: EndReason::Resignation,
+            flats: self.flat_counts(),
+        }
+    }
+
+    /// The result of `loser` running out of time in this position.
+    #[allow(dead_code)]
+    #[must_use]
+    pub fn time_forfeit(&self, loser: Color) -> GameResult {
+        GameResult {
+            winner: Some(!loser),
+            reason: EndReason::TimeForfeit,
+            flats: self.flat_counts(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn ptn_results() {
+        let cases = [
+            (
+                "2,x2,2,x2/x3,2,2,x/x,2,221,2,x,1/21,1,1,1C,121,1/x2,112C,x3/x5,1 2 16",
+                "R-0",
+                EndReason::Road,
+            ),
+            (
+                "2,2,2,2,2,x/x4,2,x/x,2,2,2,2,x/x,2,x4/x,2,2,2,2,2/x6 2 16",
+                "0-R",
+                EndReason::Road,
+            ),
+            ("2,1,2/1,2,1/2,1,2 1 5", "0-F", EndReason::BoardFull),
+            ("21,1,2/1,2,1/2,1,2 1 5", "F-0", EndReason::BoardFull),
+            ("21,1,2/1,2,1S/2,1,2 1 5", "1/2-1/2", EndReason::BoardFull),
+            ("21,2,x/x,1,2/1111111,1,2 1 8", "F-0", EndReason::ReservesExhausted),
+        ];
+        for (tps, ptn, reason) in cases {
+            let mut pos = Position::from_str(tps).unwrap();
+            pos.set_half_komi(0);
+            let result = pos.terminal().unwrap();
+            assert_eq!(result.to_string(), ptn, "{tps}");
+            assert_eq!(result.reason(), reason, "{tps}");
+        }
+    }
+
+    #[test]
+    fn flat_counts() {
+        let pos = Position::from_str("21,2,x/x,1,2/1111111,1,2 1 8").unwrap();
+        let result = pos.terminal().unwrap();
+        assert_eq!(result.flats(Color::P1), 4);
+        assert_eq!(result.flats(Color::P2), 3);
+        assert!(result.reason().is_flat_count());
+    }
+
+    #[test]
+    fn resignation_and_time() {
+        let pos = Position::default();
+        assert_eq!(pos.resignation(Color::P1).to_string(), "0-1");
+        assert_eq!(pos.resignation(Color::P2).to_string(), "1-0");
+        assert_eq!(pos.time_forfeit(Color::P2).to_string(), "1-0");
+        assert_eq!(pos.time_forfeit(Color::P1).winner(), Some(Color::P2));
+    }
+}
@@ -1,48 +1,92 @@
-use super::Position;
-use crate::{
-    KOMI,
-    types::{Bitboard, Color},
-};
-use std::{cmp::Ordering, simd::u64x4};
-
-#[derive(Copy, Clone, Debug)]
-pub enum Terminal {
-    P1Won,
-    P2Won,
-    Draw,
-}
+use super::{MoveList, Position};
+use crate::types::{Bitboard, Color, Move, PieceType};
+use std::simd::u64x4;
+
+impl Position {
+    pub fn road_completed(&self, c: Color) -> bool {
+        self.has_road(self.roads(c))
+    }
 
-impl Terminal {
-    fn winner(self) -> Option<Color> {
-        match self {
-            Terminal::P1Won => Some(Color::P1),
-            Terminal::P2Won => Some(Color::P2),
-            Terminal::Draw => None,
+    /// Empty squares where a road piece of `c` would complete a road for `c`, whether or not `c`
+    /// is the side to move or has a piece left to place there.
+    #[must_use]
+    pub fn road_threats(&self, c: Color) -> Bitboard {
+        let bb = u64x4::splat(self.roads(c).0);
+        let edges = self.edges();
+        let mut curr = bb & edges;
+        loop {
+            let next = grow(curr) & bb;
+            if next == curr {
+                break;
+            }
+            curr = next;
         }
+
+        // A piece completes a road if it touches both edges of a pair, either directly or through
+        // the groups that reach them.
+        let [north, east, south, west] = (grow(curr) | edges).to_array();
+        Bitboard((north & south) | (east & west)) & self.empty()
     }
-}
 
-impl Position {
-    pub fn road_completed(&self, c: Color) -> bool {
-        let bb = self.roads(c);
+    /// Collects every legal move that wins the game on the spot for the side to move, by road or
+    /// on flats.
+    pub fn winning_moves(&self, ml: &mut MoveList) {
+        ml.clear();
+        // Opening placements are of the opponent's pieces and never end the game.
+        if self.ply < 2 {
+            return;
+        }
+
+        let mut moves = MoveList::new();
+        self.generate_moves(&mut moves);
 
-        const NORTH_EDGE: Bitboard = Bitboard::rank_mask(5);
-        const EAST_EDGE: Bitboard = Bitboard::file_mask(5);
-        const SOUTH_EDGE: Bitboard = Bitboard::rank_mask(0);
-        const WEST_EDGE: Bitboard = Bitboard::file_mask(0);
-        const EDGES: u64x4 =
-            u64x4::from_array([NORTH_EDGE.0, EAST_EDGE.0, SOUTH_EDGE.0, WEST_EDGE.0]);
+        let stm = self.stm;
+        let empty = self.empty().count_ones() as usize;
+        let threats = self.road_threats(stm);
+        let last_piece = self.remaining_stones(stm) as u32 + self.remaining_caps(stm) as u32 == 1;
+
+        // A spread can only win by road if the squares it could reach, together with the road
+        // pieces already in place, connect two edges. Reach is over-estimated as everything within
+        // the height of a stack in any direction. It can only win on flats by filling the board.
+        let mut reach = self.roads(stm) | self.color(stm);
+        for sq in self.color(stm) {
+            let mut squares = sq.to_bitboard();
+            for _ in 0..self.height(sq).min(self.carry_limit() as u8) {
+                squares |= squares.adjacent();
+            }
+            reach |= squares;
+        }
+        let spreads_may_win = self.has_road(reach & self.board()) || empty <= self.carry_limit();
 
+        let mut pos = self.clone();
+        for mv in moves {
+            let wins = if mv.is_place() {
+                (mv.piece_type() != PieceType::Wall && threats.get(mv.sq()))
+                    || ((empty == 1 || last_piece) && pos.wins_with(mv))
+            } else {
+                spreads_may_win && pos.wins_with(mv)
+            };
+            if wins {
+                ml.push(mv);
+            }
+        }
+    }
+
+    fn wins_with(&mut self, mv: Move) -> bool {
+        let mover = self.stm;
+        let undo = self.do_move(mv);
+        let wins = self.terminal().is_some_and(|result| result.winner() == Some(mover));
+        self.undo_move(mv, undo);
+        wins
+    }
+
+    /// Whether the squares in `bb` connect opposite edges of the board.
+    fn has_road(&self, bb: Bitboard) -> bool {
         let bb = u64x4::splat(bb.0);
-        let mut curr = bb & EDGES;
+        let mut curr = bb & self.edges();
 
         loop {
-            let next = curr
-                | ((curr & u64x4::splat((!EAST_EDGE).0)) << 1)
-                | ((curr & u64x4::splat((!WEST_EDGE).0)) >> 1)
-                | (curr << 6)
-                | (curr >> 6);
-            let next = next & bb;
+            let next = grow(curr) & bb;
 
             if next == curr {
                 return false;
@@ -56,33 +100,40 @@ impl Position {
         }
     }
 
-    pub fn terminal(&self) -> Option<Terminal> {
-        if self.road_completed(Color::P1) {
-            Some(Terminal::P1Won)
-        } else if self.road_completed(Color::P2) {
-            Some(Terminal::P2Won)
-        } else if self
-            .remaining_stones
-            .iter()
-            .zip(self.remaining_caps.iter())
-            .any(|x| x == (&0, &0))
-        {
-            let p1flats = self.flats(Color::P1).count_ones();
-            let p2flats = self.flats(Color::P2).count_ones();
-            match p1flats.cmp(&(p2flats + KOMI)) {
-                Ordering::Less => Some(Terminal::P2Won),
-                Ordering::Greater => Some(Terminal::P1Won),
-                Ordering::Equal => Some(Terminal::Draw),
-            }
-        } else {
-            None
-        }
+    /// The north, east, south and west edges of the board, in that order so that opposite edges
+    /// are two lanes apart.
+    fn edges(&self) -> u64x4 {
+        let board = self.board();
+        let edge = self.size() - 1;
+        let north_edge = Bitboard::rank_mask(edge) & board;
+        let east_edge = Bitboard::file_mask(edge) & board;
+        let south_edge = Bitboard::rank_mask(0) & board;
+        let west_edge = Bitboard::file_mask(0) & board;
+        u64x4::from_array([north_edge.0, east_edge.0, south_edge.0, west_edge.0])
     }
 }
 
+/// Adds the squares adjacent to each lane's set. Steps off the east edge of a smaller board land on
+/// squares that are never set in the sets being flooded, so only wrapping around the 8-wide layout
+/// needs masking.
+fn grow(curr: u64x4) -> u64x4 {
+    const NOT_H_FILE: u64 = !Bitboard::file_mask(7).0;
+    const NOT_A_FILE: u64 = !Bitboard::file_mask(0).0;
+
+    curr | ((curr & u64x4::splat(NOT_H_FILE)) << 1)
+        | ((curr & u64x4::splat(NOT_A_FILE)) >> 1)
+        | (curr << 8)
+        | (curr >> 8)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        position::{EndReason, GameResult},
+        types::{Move, Square},
+    };
+    use std::collections::HashSet;
     use std::str::FromStr;
 
     #[test]
@@ -109,6 +160,87 @@ mod tests {
         assert!(!pos.road_completed(Color::P2));
     }
 
+    #[test]
+    fn board_sizes() {
+        let cases = [
+            ("1,x2/1,x2/1,x2 2 4", true),
+            ("1,x2/2,x2/1,x2 2 4", false),
+            ("x4,1/x4,1/x4,1/x4,1/x4,1 2 6", true),
+            ("x4,1/x4,1/x4,1/x4,1S/x4,1 2 6", false),
+            ("x8/x8/x8/x8/x8/x8/x8/1,1,1,1,1,1,1,1 2 9", true),
+            ("x8/x8/x8/x8/x8/x8/1,x7/x,1,1,1,1,1,1,1 2 9", false),
+            ("1,x7/1,x7/1,x7/1,x7/1,x7/1,x7/1,x7/x7,1 2 9", false),
+        ];
+        for (tps, completed) in cases {
+            let pos = Position::from_str(tps).unwrap();
+            assert_eq!(pos.road_completed(Color::P1), completed, "{tps}");
+            assert!(!pos.road_completed(Color::P2));
+        }
+    }
+
+    #[test]
+    fn komi() {
+        let tps = "21,2,x/x,1,2/1111111,1,2 1 8";
+        let cases = [
+            (0, Some(Color::P1)),
+            (1, Some(Color::P1)),
+            (2, None),
+            (3, Some(Color::P2)),
+            (4, Some(Color::P2)),
+        ];
+        for (half_komi, winner) in cases {
+            let mut pos = Position::from_str(tps).unwrap();
+            pos.set_half_komi(half_komi);
+            assert_eq!(pos.terminal().unwrap().winner(), winner, "{half_komi}");
+        }
+    }
+
+    #[test]
+    fn board_full() {
+        let cases = [
+            ("2,1,2/1,2,1/2,1,2 1 5", Some(Some(Color::P2))),
+            ("21,1,2/1,2,1/2,1,2 1 5", Some(Some(Color::P1))),
+            ("21,1,2/1,2,1S/2,1,2 1 5", Some(None)),
+            ("x,1,2/1,2,1/2,1,2 1 5", None),
+        ];
+        for (tps, winner) in cases {
+            let mut pos = Position::from_str(tps).unwrap();
+            pos.set_half_komi(0);
+            assert!(!pos.road_completed(Color::P1));
+            assert!(!pos.road_completed(Color::P2));
+            assert_eq!(pos.terminal().map(GameResult::winner), winner, "{tps}");
+        }
+    }
+
+    #[test]
+    fn double_roads() {
+        let cases = [
+            (Color::P1, "2,2,2,2,2,2/x6/x6/x6/x6/1,1,1,1,1,1 2 7"),
+            (Color::P2, "2,2,2,2,2,2/x6/x6/x6/x6/1,1,1,1,1,1 1 8"),
+            (Color::P1, "2,x3,1/2,x3,1/2,x3,1/2,x3,1/2,x3,1 2 9"),
+            (Color::P2, "2,x3,1/2,x3,1/2,x3,1/2,x3,1/2,x3,1 1 10"),
+        ];
+        for (c, tps) in cases {
+            let pos = Position::from_str(tps).unwrap();
+            assert!(pos.road_completed(Color::P1));
+            assert!(pos.road_completed(Color::P2));
+            assert_eq!(pos.terminal().unwrap().winner(), Some(c), "{tps}");
+        }
+
+        let cases = [
+            (Color::P1, "x6/21,x5/x,2,2,2,2,2/x,1,1,1,1,1/x6/x6 1 10", "2a5-11"),
+            (Color::P2, "x6/12,x5/x,1,1,1,1,1/x,2,2,2,2,2/x6/x6 2 10", "2a5-11"),
+        ];
+        for (c, tps, mv) in cases {
+            let pos = Position::from_str(tps).unwrap();
+            assert!(pos.terminal().is_none());
+            let pos = pos.make_move(Move::from_str(mv).unwrap());
+            assert!(pos.road_completed(Color::P1));
+            assert!(pos.road_completed(Color::P2));
+            assert_eq!(pos.terminal().unwrap().winner(), Some(c), "{tps} {mv}");
+        }
+    }
+
     #[test]
     fn will_tests() {
         let cases = [
@@ -132,10 +264,7 @@ mod tests {
                 Color::P2,
                 "2,2,112,2,21,1S/x2,2,21221,2S,x/2S,1,22,1,211121C,22/122S,x,2,x,1,1S/11,2S,2,12,112,122C/1211S,11,122S,1,x,2S 1 34",
             ),
-            (
-                Color::P2,
-                "2,2,2,2,2,x/x4,2,x/x,2,2,2,2,x/x,2,x4/x,2,2,2,2,2/x6 2 16",
-            ),
+            (Color::P2, "2,2,2,2,2,x/x4,2,x/x,2,2,2,2,x/x,2,x4/x,2,2,2,2,2/x6 2 16"),
             (
                 Color::P1,
                 "x,1,x4/x,1,x,1,1,1/x,1,x,1,x,1/x,1,x,1,x,1/x,1,1,1,x,1/x5,1 1 16",
@@ -148,4 +277,116 @@ mod tests {
             assert_eq!(pos.terminal().unwrap().winner(), Some(c));
         }
     }
+
+    /// The same board with the other player to move.
+    fn flip_stm(pos: &Position) -> Position {
+        let tps = pos.to_string();
+        let [board, stm, fullmove] = tps.split(' ').collect::<Vec<_>>()[..] else {
+            unreachable!()
+        };
+        let stm = if stm == "1" { "2" } else { "1" };
+        Position::parse_from_parts(board, stm, fullmove).unwrap()
+    }
+
+    fn check_threats(pos: &Position) {
+        for c in [Color::P1, Color::P2] {
+            let pos = if pos.stm() == c { pos.clone() } else { flip_stm(pos) };
+            let pt = if pos.remaining_stones(c) > 0 {
+                PieceType::Flat
+            } else {
+                PieceType::Cap
+            };
+            let expected = pos
+                .empty()
+                .filter(|&sq| pos.make_move(Move::place(pt, sq)).road_completed(c))
+                .fold(Bitboard::default(), |bb, sq| bb | sq.to_bitboard());
+            assert!(pos.road_threats(c) == expected, "{pos} {c}");
+        }
+    }
+
+    fn check_winning_moves(pos: &Position) {
+        let mut moves = MoveList::new();
+        pos.generate_moves(&mut moves);
+        let expected: HashSet<Move> = moves
+            .into_iter()
+            .filter(|&mv| {
+                pos.make_move(mv)
+                    .terminal()
+                    .is_some_and(|result| result.winner() == Some(pos.stm()))
+            })
+            .collect();
+
+        let mut winning = MoveList::new();
+        pos.winning_moves(&mut winning);
+        let actual: HashSet<Move> = winning.iter().copied().collect();
+        assert_eq!(actual.len(), winning.len());
+        assert!(actual == expected, "{pos}");
+    }
+
+    #[test]
+    fn threats() {
+        let pos = Position::from_str("x5/x5/x5/1,1,1,1,x/2,2,2,x,2 1 5").unwrap();
+        assert!(pos.road_threats(Color::P1) == Square::E2.to_bitboard());
+        assert!(pos.road_threats(Color::P2) == Square::D1.to_bitboard());
+
+        let pos = Position::from_str("x,1,x3/x,1,x3/x5/x,1,x3/x,1,2,2,2 1 6").unwrap();
+        assert!(pos.road_threats(Color::P1) == Square::B3.to_bitboard());
+        assert!(pos.road_threats(Color::P2).is_empty());
+
+        for tps in [
+            "x5/x5/x5/1,1,1,1,x/2,2,2,x,2 1 5",
+            "x,1,x3/x,1,x3/x5/x,1,x3/x,1,2,2,2 1 6",
+            "x6/x6/x6/x,1,1,1,1,1S/x6/2,2,2,2,2,x 2 6",
+            "x6/2C,1,1,1,1,1/2,x,111121S,x3/2,x,11,x,1,x/2,1C,12,2,2,2/x,112,x4 2 22",
+        ] {
+            let pos = Position::from_str(tps).unwrap();
+            check_threats(&pos);
+            check_winning_moves(&pos);
+        }
+    }
+
+    #[test]
+    fn winning_spreads() {
+        // 2a5-11 completes roads for both players, which wins for the mover.
+        let pos = Position::from_str("x6/21,x5/x,2,2,2,2,2/x,1,1,1,1,1/x6/x6 1 10").unwrap();
+        let mut moves = MoveList::new();
+        pos.winning_moves(&mut moves);
+        assert!(moves.contains(&Move::from_str("2a5-11").unwrap()));
+        check_winning_moves(&pos);
+
+        // Filling the last empty square with a spread ends the game on flats.
+        let mut pos = Position::from_str("2,1,2/1,2,1/211,x,2 1 6").unwrap();
+        pos.set_half_komi(0);
+        let mv = Move::from_str("a1>").unwrap();
+        assert_eq!(pos.make_move(mv).terminal().unwrap().reason(), EndReason::BoardFull);
+        pos.winning_moves(&mut moves);
+        assert!(moves.contains(&mv));
+        check_winning_moves(&pos);
+    }
+
+    #[test]
+    fn random_games() {
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut random = move |n: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % n as u64) as usize
+        };
+
+        for size in Position::MIN_SIZE..=Position::MAX_SIZE {
+            for _ in 0..20 {
+                let mut pos = Position::startpos(size);
+                let mut moves = MoveList::new();
+                while pos.terminal().is_none() {
+                    if pos.ply() >= 2 {
+                        check_threats(&pos);
+                    }
+                    check_winning_moves(&pos);
+                    pos.generate_moves(&mut moves);
+                    pos = pos.make_move(moves[random(moves.len())]);
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,121 @@
+use super::Position;
+use crate::types::{Bitboard, Color, Piece, PieceType, Square, Symmetry};
+use std::cmp::Ordering;
+
+impl Position {
+    #[must_use]
+    pub fn transform(&self, sym: Symmetry) -> Position {
+        let mut pos = self.clone();
+        pos.colors = [Bitboard::default(); Color::NUM];
+        pos.tops = [Bitboard::default(); PieceType::NUM];
+        pos.mailbox = [Piece::None; Square::NUM];
+        pos.stacks = [0; Square::NUM];
+        pos.heights = [0; Square::NUM];
+
+        for sq in self.occupied() {
+            let to = sq.transform(sym, self.size());
+            let piece = self.mailbox[sq.to_index()];
+
+            pos.mailbox[to.to_index()] = piece;
+            pos.stacks[to.to_index()] = self.stacks[sq.to_index()];
+            pos.heights[to.to_index()] = self.heights[sq.to_index()];
+            pos.colors[piece.color().to_index()].set(to);
+            pos.tops[piece.piece_type().to_index()].set(to);
+        }
+
+        pos.key = pos.compute_key();
+        pos
+    }
+
+    /// The smallest of the eight transforms of this position, together with the symmetry that
+    /// produces it. Boards are ordered square by square on height, then stack contents, then the
+    /// top piece.
+    #[must_use]
+    pub fn canonical(&self) -> (Position, Symmetry) {
+        Symmetry::ALL
+            .iter()
+            .map(|&sym| (self.transform(sym), sym))
+            .min_by(|(a, _), (b, _)| a.board_cmp(b))
+            .unwrap()
+    }
+
+    fn board_cmp(&self, other: &Position) -> Ordering {
+        (0..Square::NUM)
+            .map(|i| {
+                let a = (self.heights[i], self.stacks[i], self.mailbox[i].to_index());
+                let b = (other.heights[i], other.stacks[i], other.mailbox[i].to_index());
+                a.cmp(&b)
+            })
+            .find(|&ord| ord != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{position::MoveList, types::Move};
+    use std::{collections::HashSet, str::FromStr};
+
+    const CASES: [&str; 6] = [
+        "x6/x6/x6/x6/x6/1,x5 2 1",
+        "x6/2C,1,1,1,1,1/2,x,111121S,x3/2,x,11,x,1,x/2,1C,12,2,2,2/x,112,x4 2 22",
+        "x6/x4,1S,x/x2,21111S,1C,22122C,x/x6/x6/x6 2 11",
+        "2,x,2,111S,2,12/2,122S,2122,1S,x,1/x,111,1,11S,x2/21122112C,x,212S,2S,2,1212S/1,112S,21221S,2S,x2/21,222,x,12S,x2 2 30",
+        "x,1,2,x2/x,21C,12S,x,1/x5/2,x,12C,x2/x4,1 1 9",
+        "2,1,x/x,21S,x/1,x,2 2 4",
+    ];
+
+    #[test]
+    fn moves_commute() {
+        for tps in CASES {
+            let pos = Position::from_str(tps).unwrap();
+            let mut moves = MoveList::new();
+            pos.generate_moves(&mut moves);
+
+            for sym in Symmetry::ALL {
+                let transformed = pos.transform(sym);
+                transformed.verify();
+
+                let mut expected = MoveList::new();
+                transformed.generate_moves(&mut expected);
+                let expected: HashSet<Move> = expected.into_iter().collect();
+                let actual: HashSet<Move> = moves.iter().map(|mv| mv.transform(sym, pos.size())).collect();
+                assert!(expected == actual, "{tps} {sym:?}");
+
+                for &mv in moves.iter() {
+                    let a = pos.make_move(mv).transform(sym);
+                    let b = transformed.make_move(mv.transform(sym, pos.size()));
+                    assert_eq!(a.to_string(), b.to_string(), "{tps} {sym:?} {mv}");
+                    assert_eq!(a.key(), b.key());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn canonical() {
+        for tps in CASES {
+            let pos = Position::from_str(tps).unwrap();
+            let (canonical, sym) = pos.canonical();
+            assert!(pos.transform(sym) == canonical);
+            assert!(canonical.transform(sym.inverse()) == pos);
+
+            for sym in Symmetry::ALL {
+                let (other, _) = pos.transform(sym).canonical();
+                assert!(other == canonical, "{tps} {sym:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let pos = Position::from_str(CASES[1]).unwrap();
+        let tps = [
+            "x,2,2,2,2C,x/112,1C,x2,1,x/x,12,11,111121S,1,x/x,2,x2,1,x/x,2,1,x,1,x/x,2,x2,1,x 2 22",
+            "x4,112,x/2,2,2,12,1C,2/x,1,x,11,x,2/x3,111121S,x,2/1,1,1,1,1,2C/x6 2 22",
+        ];
+        assert_eq!(pos.transform(Symmetry::Rotate90).to_string(), tps[0]);
+        assert_eq!(pos.transform(Symmetry::Rotate180).to_string(), tps[1]);
+    }
+}
@@ -44,25 +44,26 @@ impl FromStr for Position {
 impl Position {
     pub fn parse_from_parts(board: &str, stm: &str, fullmove: &str) -> Result<Position, TpsError> {
         let ranks: Vec<_> = board.split('/').collect();
-        if ranks.len() != 6 {
+        let size = ranks.len();
+        if !Position::is_valid_size(size) {
             return Err(TpsError::WrongRankCount);
         }
 
         let mut colors = [Bitboard::default(); Color::NUM];
         let mut tops = [Bitboard::default(); PieceType::NUM];
         let mut mailbox = [Piece::None; Square::NUM];
-        let mut stacks = [0u64; Square::NUM];
+        let mut stacks = [0u128; Square::NUM];
         let mut heights = [0u8; Square::NUM];
         let mut stone_count = [0u8; Color::NUM];
         let mut cap_count = [0u8; Color::NUM];
 
         for (inv_rank, rank_str) in ranks.iter().enumerate() {
-            let rank = 5 - inv_rank;
+            let rank = size - 1 - inv_rank;
             let elements: Vec<_> = rank_str.split(',').collect();
             let mut file = 0;
 
             for element in elements {
-                if file >= 6 {
+                if file >= size {
                     return Err(TpsError::WrongFileCount);
                 }
 
@@ -82,7 +83,7 @@ impl Position {
                     file += 1;
 
                     let mut height: u8 = 0;
-                    let mut stack: u64 = 0;
+                    let mut stack: u128 = 0;
                     let mut top = PieceType::None;
 
                     for ch in element.chars() {
@@ -128,13 +129,13 @@ impl Position {
                 }
             }
 
-            if file != 6 {
+            if file != size {
                 return Err(TpsError::WrongFileCount);
             }
         }
 
-        let max_stones = Position::STARTING_STONES;
-        let max_caps = Position::STARTING_CAPS;
+        let max_stones = Position::starting_stones(size);
+        let max_caps = Position::starting_caps(size);
 
         if stone_count.iter().any(|&x| x > max_stones) {
             return Err(TpsError::TooManyStones);
@@ -157,7 +158,9 @@ impl Position {
         };
         let ply = (fullmove - 1) * 2 + stm.to_index() as u16;
 
-        Ok(Position {
+        let mut position = Position {
+            size: size as u8,
+            half_komi: Position::DEFAULT_HALF_KOMI,
             stm,
             ply,
             colors,
@@ -167,7 +170,11 @@ impl Position {
             heights,
             remaining_stones,
             remaining_caps,
-        })
+            key: 0,
+        };
+        position.key = position.compute_key();
+
+        Ok(position)
     }
 }
 
@@ -175,11 +182,11 @@ impl std::fmt::Display for Position {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let board = {
             let mut ranks = Vec::new();
-            for rank in (0..6).rev() {
+            for rank in (0..self.size()).rev() {
                 let mut empty_squares = 0;
                 let mut elements = Vec::new();
 
-                for file in 0..6 {
+                for file in 0..self.size() {
                     let sq = Square::from_file_and_rank(file, rank);
 
                     if self.is_empty(sq) {
@@ -254,6 +261,14 @@ mod tests {
             "x2,2,x2,1/x,122,121C,212,x2/x2,2,2,2,2C/1,x2,2,x,1/x2,1,x2,1/1,x2,2111112,x,1 2 24",
             "2,x,222212,x,2,12C/x,221S,x2,2,x/21,x3,1,x/22,221,1121S,x,1,x/2112,221S,x4/x,1C,1,1,x2 2 48",
             "2,x2,2,1,x/2,x,12,x,1,112S/2,21S,221C,211111,2,1/x,1S,x,22212C,x2/1S,2,2,x2,21/x,21121,2,12,2,2 2 36",
+            "x3/x3/x3 1 1",
+            "2,1,x/x,21S,x/1,x,2 2 4",
+            "x4/x4/x4/x4 1 1",
+            "x5/x5/x5/x5/x5 1 1",
+            "x,1,2,x2/x,21C,12S,x,1/x5/2,x,12C,x2/x4,1 1 9",
+            "x7/x7/x7/x7/x7/x7/x7 1 1",
+            "x8/x8/x8/x8/x8/x8/x8/x8 1 1",
+            "1,x7/x,2C,x6/x2,1C,x5/x8/x4,2121212121212121212121212121212121212121212121212121212121212121212121,x3/x8/x8/x7,2 2 40",
         ];
 
         for case in cases {
@@ -262,4 +277,39 @@ mod tests {
             assert_eq!(case, tps);
         }
     }
+
+    #[test]
+    fn board_size() {
+        let cases = [
+            ("x3/x3/x3 1 1", 3),
+            ("x4/x4/x4/x4 1 1", 4),
+            ("x5/x5/x5/x5/x5 1 1", 5),
+            ("x6/x6/x6/x6/x6/x6 1 1", 6),
+            ("x7/x7/x7/x7/x7/x7/x7 1 1", 7),
+            ("x8/x8/x8/x8/x8/x8/x8/x8 1 1", 8),
+        ];
+
+        for (tps, size) in cases {
+            let position = Position::from_str(tps).unwrap();
+            assert_eq!(position.size(), size);
+            position.verify();
+        }
+    }
+
+    #[test]
+    fn invalid_tps() {
+        let cases = [
+            "x2/x2 1 1",
+            "x9/x9/x9/x9/x9/x9/x9/x9/x9 1 1",
+            "x5/x5/x5/x5/x6 1 1",
+            "x5/x5/x5/x5/x4 1 1",
+            "x3/x3/1C,x2 1 2",
+            "x4/x4/x4/1C,x3 1 2",
+            "x5/x5/x5/x5/1C,2C,1C,x2 1 3",
+        ];
+
+        for tps in cases {
+            assert!(Position::from_str(tps).is_err(), "{tps}");
+        }
+    }
 }
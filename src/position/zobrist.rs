@@ -0,0 +1,229 @@
+use super::Position;
+use crate::types::{Color, PieceType, Square};
+
+/// Stacks never exceed the total number of pieces in an 8x8 game (2 * (50 + 2)).
+const MAX_HEIGHT: usize = 128;
+const MAX_STONES: usize = 50;
+const MAX_CAPS: usize = 2;
+
+struct Keys {
+    pieces: [[[u64; Color::NUM]; MAX_HEIGHT]; Square::NUM],
+    tops: [[u64; 2]; Square::NUM],
+    stones: [[u64; MAX_STONES + 1]; Color::NUM],
+    caps: [[u64; MAX_CAPS + 1]; Color::NUM],
+    stm: u64,
+}
+
+const fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E3779B97F4A7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+impl Keys {
+    const fn new() -> Keys {
+        let mut state = 0x70656E74616B6C65;
+        let mut keys = Keys {
+            pieces: [[[0; Color::NUM]; MAX_HEIGHT]; Square::NUM],
+            tops: [[0; 2]; Square::NUM],
+            stones: [[0; MAX_STONES + 1]; Color::NUM],
+            caps: [[0; MAX_CAPS + 1]; Color::NUM],
+            stm: 0,
+        };
+
+        let mut sq = 0;
+        while sq < Square::NUM {
+            let mut i = 0;
+            while i < MAX_HEIGHT {
+                keys.pieces[sq][i][0] = splitmix64(&mut state);
+                keys.pieces[sq][i][1] = splitmix64(&mut state);
+                i += 1;
+            }
+            keys.tops[sq][0] = splitmix64(&mut state);
+            keys.tops[sq][1] = splitmix64(&mut state);
+            sq += 1;
+        }
+
+        let mut c = 0;
+        while c < Color::NUM {
+            let mut i = 0;
+            while i <= MAX_STONES {
+                keys.stones[c][i] = splitmix64(&mut state);
+                i += 1;
+            }
+            let mut i = 0;
+            while i <= MAX_CAPS {
+                keys.caps[c][i] = splitmix64(&mut state);
+                i += 1;
+            }
+            c += 1;
+        }
+
+        keys.stm = splitmix64(&mut state);
+        keys
+    }
+}
+
+static KEYS: Keys = Keys::new();
+
+/// Key for a run of `count` pieces whose colors are packed top-first in `run`, with the lowest
+/// piece of the run sitting at `bottom` pieces above the board.
+#[must_use]
+pub(super) fn run_key(sq: Square, run: u128, bottom: usize, count: usize) -> u64 {
+    let pieces = &KEYS.pieces[sq.to_index()];
+    (0..count).fold(0, |key, i| {
+        let color = ((run >> i) & 1) as usize;
+        key ^ pieces[bottom + count - 1 - i][color]
+    })
+}
+
+/// Key for a wall or capstone on top of `sq`. Flats are implied by the stack contents.
+#[must_use]
+pub(super) fn top_key(sq: Square, pt: PieceType) -> u64 {
+    match pt {
+        PieceType::Wall => KEYS.tops[sq.to_index()][0],
+        PieceType::Cap => KEYS.tops[sq.to_index()][1],
+        PieceType::Flat | PieceType::None => 0,
+    }
+}
+
+#[must_use]
+pub(super) fn stones_key(c: Color, remaining: u8) -> u64 {
+    KEYS.stones[c.to_index()][remaining as usize]
+}
+
+#[must_use]
+pub(super) fn caps_key(c: Color, remaining: u8) -> u64 {
+    KEYS.caps[c.to_index()][remaining as usize]
+}
+
+#[must_use]
+pub(super) fn stm_key() -> u64 {
+    KEYS.stm
+}
+
+impl Position {
+    #[must_use]
+    pub fn key(&self) -> u64 {
+        self.key
+    }
+
+    /// Computes the Zobrist key from scratch. `make_move` keeps `key` up to date incrementally.
+    #[must_use]
+    pub(super) fn compute_key(&self) -> u64 {
+        let mut key = 0;
+
+        for sq in self.occupied() {
+            let i = sq.to_index();
+            key ^= run_key(sq, self.stacks[i], 0, self.heights[i] as usize);
+            key ^= top_key(sq, self.mailbox[i].piece_type());
+        }
+
+        for c in [Color::P1, Color::P2] {
+            key ^= stones_key(c, self.remaining_stones[c.to_index()]);
+            key ^= caps_key(c, self.remaining_caps[c.to_index()]);
+        }
+
+        if self.stm == Color::P2 {
+            key ^= stm_key();
+        }
+
+        key
+    }
+}
+
+/// Positions are equal when their boards, reserves and side to move match, so that the same
+/// position reached at different points of a game compares equal. Only whether the opening
+/// swap is still in progress is taken from the move counter.
+impl PartialEq for Position {
+    fn eq(&self, other: &Position) -> bool {
+        self.key == other.key
+            && self.size == other.size
+            && self.stm == other.stm
+            && (self.ply < 2) == (other.ply < 2)
+            && self.mailbox == other.mailbox
+            && self.stacks == other.stacks
+            && self.heights == other.heights
+            && self.remaining_stones == other.remaining_stones
+            && self.remaining_caps == other.remaining_caps
+    }
+}
+
+impl Eq for Position {}
+
+impl std::hash::Hash for Position {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        state.write_u64(self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{position::MoveList, types::Move};
+    use std::{collections::HashSet, str::FromStr};
+
+    fn walk(pos: &Position, depth: i32, keys: &mut HashSet<u64>, positions: &mut HashSet<Position>) {
+        pos.verify();
+        keys.insert(pos.key());
+        positions.insert(pos.clone());
+
+        if depth == 0 || pos.terminal().is_some() {
+            return;
+        }
+
+        let mut moves = MoveList::new();
+        pos.generate_moves(&mut moves);
+        for &mv in moves.iter() {
+            walk(&pos.make_move(mv), depth - 1, keys, positions);
+        }
+    }
+
+    #[test]
+    fn incremental_matches_full() {
+        let cases = [
+            "x6/x6/x6/x6/x6/x6 1 1",
+            "x6/x4,1S,x/x2,21111S,1C,22122C,x/x6/x6/x6 2 11",
+            "x6/x6/x6/x3,111222111222111222111222111222111222111222111222111222111222C,x2/x6/x6 2 31",
+            "2,x,2,111S,2,12/2,122S,2122,1S,x,1/x,111,1,11S,x2/21122112C,x,212S,2S,2,1212S/1,112S,21221S,2S,x2/21,222,x,12S,x2 2 30",
+            "1,x7/x,2C,x6/x2,1C,x5/x8/x4,2121212121212121212121212121212121212121212121212121212121212121212121,x3/x8/x8/x7,2 2 40",
+        ];
+        for tps in cases {
+            let pos = Position::from_str(tps).unwrap();
+            let mut keys = HashSet::new();
+            let mut positions = HashSet::new();
+            walk(&pos, 2, &mut keys, &mut positions);
+            assert_eq!(keys.len(), positions.len(), "{tps}");
+        }
+    }
+
+    #[test]
+    fn transpositions() {
+        let pos = Position::from_str("x6/x6/x6/x6/x6/x6 1 3").unwrap();
+        let a = ["a1", "b1", "c1", "d1"]
+            .iter()
+            .fold(pos.clone(), |p, m| p.make_move(Move::from_str(m).unwrap()));
+        let b = ["c1", "d1", "a1", "b1"]
+            .iter()
+            .fold(pos.clone(), |p, m| p.make_move(Move::from_str(m).unwrap()));
+        let c = ["b1", "a1", "c1", "d1"]
+            .iter()
+            .fold(pos.clone(), |p, m| p.make_move(Move::from_str(m).unwrap()));
+        assert_eq!(a.key(), b.key());
+        assert!(a == b);
+        assert_ne!(a.key(), c.key());
+        assert!(a != c);
+    }
+
+    #[test]
+    fn stack_order() {
+        let a = Position::from_str("x6/x6/x6/x6/x6/12,x5 1 3").unwrap();
+        let b = Position::from_str("x6/x6/x6/x6/x6/21,x5 1 3").unwrap();
+        let c = Position::from_str("x6/x6/x6/x6/x6/12S,x5 1 3").unwrap();
+        let d = Position::from_str("x6/x6/x6/x6/x6/12,x5 2 3").unwrap();
+        let keys: HashSet<u64> = [&a, &b, &c, &d].iter().map(|p| p.key()).collect();
+        assert_eq!(keys.len(), 4);
+    }
+}
@@ -0,0 +1,498 @@
+use super::{Limits, MAX_PLY, Pv, PvLine, SearchResult, do_move, evaluate, time::TimeManager, undo_move};
+use crate::{
+    position::{MoveList, Network, Nnue, Position},
+    tei::send,
+    types::Move,
+};
+use std::{
+    mem,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// Weight of the prior against the average value when choosing which child to explore.
+const C_PUCT: f32 = 1.5;
+/// Children that have not been visited yet are assumed to be this much worse than their parent.
+const FPU_REDUCTION: f32 = 0.2;
+/// An evaluation of this many centiflats maps to a value of tanh(1), about 0.76.
+const EVAL_SCALE: f32 = 400.0;
+/// Softmax temperature of the priors, in centiflats. Lower values trust the evaluation more.
+const PRIOR_TEMPERATURE: f32 = 60.0;
+/// How often to report progress while searching.
+const INFO_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Unexpanded,
+    Expanded,
+    /// The game is over, with the given value for the player to move.
+    Terminal(f32),
+}
+
+#[derive(Clone, Copy)]
+struct Node {
+    /// The move that leads to this node from its parent, `None` at the root.
+    mv: Option<Move>,
+    prior: f32,
+    visits: u32,
+    /// Sum of the values of the playouts through this node, for the player who made `mv`.
+    value: f32,
+    kind: Kind,
+    /// Children are stored next to each other, starting at this index.
+    first_child: u32,
+    children: u32,
+}
+
+impl Node {
+    fn new(mv: Option<Move>, prior: f32) -> Node {
+        Node {
+            mv,
+            prior,
+            visits: 0,
+            value: 0.0,
+            kind: Kind::Unexpanded,
+            first_child: 0,
+            children: 0,
+        }
+    }
+
+    fn mv(&self) -> Move {
+        self.mv.expect("the root has no move")
+    }
+
+    fn q(&self) -> f32 {
+        self.value / self.visits.max(1) as f32
+    }
+
+    fn child_range(&self) -> std::ops::Range<usize> {
+        self.first_child as usize..(self.first_child + self.children) as usize
+    }
+}
+
+/// A PUCT Monte Carlo tree search. Leaves are valued with the static evaluation, and the priors of
+/// a node's children are a softmax of their evaluations.
+///
+/// The tree is kept between searches. A search from a position up to two plies after the root of
+/// the previous one carries on with the matching subtree; any other position starts a new tree.
+pub struct Mcts {
+    nodes: Vec<Node>,
+    /// The position at the root, or `None` before the first search.
+    root: Option<Position>,
+    /// Most nodes the tree may hold.
+    capacity: usize,
+    /// Evaluates with a network instead of the handcrafted evaluation if set.
+    nnue: Option<Nnue>,
+}
+
+impl Mcts {
+    /// Creates an empty tree that grows to at most `hash_mb` megabytes.
+    #[must_use]
+    pub fn new(hash_mb: usize) -> Mcts {
+        Mcts {
+            nodes: Vec::new(),
+            root: None,
+            capacity: (hash_mb << 20) / mem::size_of::<Node>(),
+            nnue: None,
+        }
+    }
+
+    /// Makes the following searches evaluate with `network`, or with the handcrafted evaluation if
+    /// it is `None`. The tree keeps the values found with the previous evaluation.
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.nnue = network.map(Nnue::new);
+    }
+
+    /// Runs playouts from `pos` until one of the `limits` is reached or `stop` is raised, printing
+    /// an `info` line every second and at the end. Depth limits do not apply, and a node limit
+    /// counts playouts. While `ponder` is raised the clock is ignored, and it starts once the flag
+    /// is lowered. Returns `None` if the game is already over.
+    pub fn search(
+        &mut self,
+        pos: &Position,
+        limits: &Limits,
+        start: Instant,
+        stop: &AtomicBool,
+        ponder: &AtomicBool,
+    ) -> Option<SearchResult> {
+        if pos.terminal().is_some() {
+            return None;
+        }
+        self.set_root(pos);
+        if let Some(nnue) = &mut self.nnue {
+            nnue.refresh(pos);
+        }
+
+        let time = (!limits.infinite).then(|| TimeManager::new(limits, pos)).flatten();
+        let movetime = (!limits.infinite)
+            .then(|| limits.movetime.map(|time| time.saturating_sub(limits.move_overhead)))
+            .flatten();
+        let deadline = [movetime, time.map(|time| time.soft())].into_iter().flatten().min();
+
+        let mut clock_start = start;
+        let mut pondering = ponder.load(Ordering::Relaxed);
+        let mut last_info = start;
+        let mut playouts = 0;
+        loop {
+            if pondering && !ponder.load(Ordering::Relaxed) {
+                pondering = false;
+                clock_start = Instant::now();
+            }
+            // Playouts go on until the root has a visited child, so that there is always a move to
+            // play. A new root takes two: one to expand it and one to visit a child.
+            if self.has_move()
+                && (stop.load(Ordering::Relaxed)
+                    || limits.nodes.is_some_and(|nodes| playouts >= nodes)
+                    || (!pondering && deadline.is_some_and(|time| clock_start.elapsed() >= time)))
+            {
+                break;
+            }
+            self.playout(pos);
+            playouts += 1;
+
+            if last_info.elapsed() >= INFO_INTERVAL {
+                last_info = Instant::now();
+                self.print_info(start, playouts);
+            }
+        }
+
+        self.print_info(start, playouts);
+        let (score, pv) = self.principal_variation();
+        Some(SearchResult {
+            best_move: pv[0],
+            depth: pv.len() as i32,
+            nodes: playouts,
+            lines: vec![PvLine { score, pv }],
+        })
+    }
+
+    /// Makes `pos` the root, keeping the subtree below it if it is at most two plies deeper than
+    /// the current root.
+    fn set_root(&mut self, pos: &Position) {
+        let same = |other: &Position| other.key() == pos.key() && other.half_komi() == pos.half_komi();
+        let Some(root) = self.root.as_ref().filter(|_| !self.nodes.is_empty()) else {
+            return self.reset(pos);
+        };
+        if same(root) {
+            return;
+        }
+
+        for child in self.nodes[0].child_range() {
+            let after = root.make_move(self.nodes[child].mv());
+            if same(&after) {
+                return self.reroot(child, pos);
+            }
+            for grandchild in self.nodes[child].child_range() {
+                if same(&after.make_move(self.nodes[grandchild].mv())) {
+                    return self.reroot(grandchild, pos);
+                }
+            }
+        }
+        self.reset(pos);
+    }
+
+    /// Whether the root has been expanded and one of its children visited. A root carried over from
+    /// an earlier search may have been visited often without being expanded, once the tree was full.
+    fn has_move(&self) -> bool {
+        let root = &self.nodes[0];
+        matches!(root.kind, Kind::Expanded) && self.nodes[root.child_range()].iter().any(|child| child.visits > 0)
+    }
+
+    /// Throws the tree away, so that the next search starts a new one.
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.root = None;
+    }
+
+    fn reset(&mut self, pos: &Position) {
+        self.nodes.clear();
+        self.nodes.push(Node::new(None, 1.0));
+        self.root = Some(pos.clone());
+    }
+
+    /// Moves the subtree below `index` to the front of a new arena, breadth first so that the
+    /// children of every node stay next to each other.
+    fn reroot(&mut self, index: usize, pos: &Position) {
+        let mut nodes = vec![self.nodes[index]];
+        let mut i = 0;
+        while i < nodes.len() {
+            let range = nodes[i].child_range();
+            nodes[i].first_child = nodes.len() as u32;
+            nodes.extend_from_slice(&self.nodes[range]);
+            i += 1;
+        }
+        self.nodes = nodes;
+        self.root = Some(pos.clone());
+    }
+
+    /// Walks down the tree from the root, expands the leaf it reaches and backs its value up. Once
+    /// the tree is full, leaves are still evaluated but no longer expanded.
+    fn playout(&mut self, root: &Position) {
+        let mut pos = root.clone();
+        if let Some(nnue) = &mut self.nnue {
+            nnue.rewind();
+        }
+        let mut path = vec![0];
+        let mut index = 0;
+
+        // The value of the leaf for the player to move there.
+        let value = loop {
+            match self.nodes[index].kind {
+                Kind::Expanded => {
+                    index = self.select(index);
+                    do_move(self.nnue.as_mut(), &mut pos, self.nodes[index].mv());
+                    path.push(index);
+                }
+                Kind::Terminal(value) => break value,
+                Kind::Unexpanded => {
+                    if let Some(result) = pos.terminal() {
+                        let value = match result.winner() {
+                            Some(winner) if winner == pos.stm() => 1.0,
+                            Some(_) => -1.0,
+                            None => 0.0,
+                        };
+                        self.nodes[index].kind = Kind::Terminal(value);
+                        break value;
+                    }
+                    self.expand(index, &mut pos);
+                    break (evaluate(self.nnue.as_ref(), &pos) as f32 / EVAL_SCALE).tanh();
+                }
+            }
+        };
+
+        // Each node's value is for the player who moved into it, the opponent of the one to move.
+        let mut value = -value;
+        for &index in path.iter().rev() {
+            let node = &mut self.nodes[index];
+            node.visits += 1;
+            node.value += value;
+            value = -value;
+        }
+    }
+
+    /// The child of `index` with the highest upper confidence bound.
+    fn select(&self, index: usize) -> usize {
+        let parent = &self.nodes[index];
+        let explore = C_PUCT * (parent.visits as f32).sqrt();
+        let fpu = -parent.q() - FPU_REDUCTION;
+
+        let ucb = |child: &Node| {
+            let q = if child.visits == 0 { fpu } else { child.q() };
+            q + explore * child.prior / (1 + child.visits) as f32
+        };
+        parent
+            .child_range()
+            .max_by(|&a, &b| ucb(&self.nodes[a]).total_cmp(&ucb(&self.nodes[b])))
+            .unwrap()
+    }
+
+    /// Adds the children of `index`, whose position is `pos` and not over, unless they do not fit
+    /// in the tree. The root is always expanded, so that there is a move to play.
+    fn expand(&mut self, index: usize, pos: &mut Position) {
+        let mut moves = MoveList::new();
+        pos.generate_moves(&mut moves);
+        if index > 0 && self.nodes.len() + moves.len() > self.capacity {
+            return;
+        }
+
+        // Scores of the moves for the player making them. Moves that end the game are clamped to
+        // a large but finite score so that the softmax stays well defined.
+        let scores: Vec<f32> = moves
+            .iter()
+            .map(|&mv| {
+                let undo = do_move(self.nnue.as_mut(), pos, mv);
+                let score = match pos.terminal() {
+                    Some(result) if result.winner() == Some(!pos.stm()) => 1000.0,
+                    Some(result) if result.winner() == Some(pos.stm()) => -1000.0,
+                    _ => -evaluate(self.nnue.as_ref(), pos) as f32,
+                };
+                undo_move(self.nnue.as_mut(), pos, mv, undo);
+                score.clamp(-1000.0, 1000.0)
+            })
+            .collect();
+        let max = scores.iter().copied().fold(f32::MIN, f32::max);
+        let weights: Vec<f32> = scores
+            .iter()
+            .map(|score| ((score - max) / PRIOR_TEMPERATURE).exp())
+            .collect();
+        let sum: f32 = weights.iter().sum();
+
+        let first_child = self.nodes.len() as u32;
+        let node = &mut self.nodes[index];
+        node.kind = Kind::Expanded;
+        node.first_child = first_child;
+        node.children = moves.len() as u32;
+        self.nodes.extend(
+            moves
+                .iter()
+                .zip(weights)
+                .map(|(&mv, weight)| Node::new(Some(mv), weight / sum)),
+        );
+    }
+
+    /// The line of most visited children from the root, with the score of its first move in
+    /// centiflats.
+    fn principal_variation(&self) -> (i32, Pv) {
+        let mut pv = Pv::new();
+        let mut index = 0;
+        while self.nodes[index].children > 0 && pv.len() < MAX_PLY {
+            let best = self.nodes[index]
+                .child_range()
+                .max_by_key(|&child| self.nodes[child].visits)
+                .unwrap();
+            if self.nodes[best].visits == 0 {
+                break;
+            }
+            pv.push(self.nodes[best].mv());
+            index = best;
+        }
+
+        let q = self.nodes[self.nodes[0].child_range()]
+            .iter()
+            .find(|child| child.mv == pv.first().copied())
+            .map_or(0.0, Node::q);
+        let score = (q.clamp(-0.999, 0.999).atanh() * EVAL_SCALE).round() as i32;
+        (score, pv)
+    }
+
+    fn print_info(&self, start: Instant, playouts: u64) {
+        let elapsed = start.elapsed();
+        let nps = (playouts as f64 / elapsed.as_secs_f64().max(1e-6)) as u64;
+        let (score, pv) = self.principal_variation();
+        let pv: Vec<String> = pv.iter().map(|mv| mv.to_string()).collect();
+        send!(
+            "info depth {} score cp {score} nodes {playouts} nps {nps} time {} pv {}",
+            pv.len(),
+            elapsed.as_millis(),
+            pv.join(" ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn search(mcts: &mut Mcts, pos: &Position, playouts: u64) -> SearchResult {
+        let limits = Limits {
+            nodes: Some(playouts),
+            ..Limits::default()
+        };
+        let flag = AtomicBool::new(false);
+        mcts.search(pos, &limits, Instant::now(), &flag, &flag).unwrap()
+    }
+
+    #[test]
+    fn road_in_one() {
+        let pos = Position::from_str("x5/x5/x5/1,1,1,1,x/2,2,2,2,x 1 5").unwrap();
+        let result = search(&mut Mcts::new(16), &pos, 500);
+        assert_eq!(result.pv().len(), 1);
+        assert_eq!(
+            pos.make_move(result.best_move).terminal().unwrap().winner(),
+            Some(pos.stm())
+        );
+    }
+
+    #[test]
+    fn blocks_road() {
+        let pos = Position::from_str("x5/x5/x5/1,1,1,1,x/2,2,x3 2 4").unwrap();
+        let result = search(&mut Mcts::new(16), &pos, 3000);
+
+        let pos = pos.make_move(result.best_move);
+        let mut moves = MoveList::new();
+        pos.generate_moves(&mut moves);
+        assert!(moves.iter().all(|&mv| pos.make_move(mv).terminal().is_none()));
+    }
+
+    #[test]
+    fn visit_counts() {
+        let mut mcts = Mcts::new(16);
+        let result = search(&mut mcts, &Position::default(), 1000);
+        assert_eq!(mcts.nodes[0].visits, 1000);
+
+        let root = mcts.nodes[0];
+        let most_visited = mcts.nodes[root.child_range()]
+            .iter()
+            .max_by_key(|child| child.visits)
+            .unwrap();
+        assert!(most_visited.mv() == result.best_move);
+        let visits: u32 = mcts.nodes[root.child_range()].iter().map(|child| child.visits).sum();
+        assert_eq!(visits, root.visits - 1);
+    }
+
+    #[test]
+    fn reuse() {
+        let mut mcts = Mcts::new(16);
+        let pos = Position::default();
+        let result = search(&mut mcts, &pos, 2000);
+        let (mv, reply) = (result.pv()[0], result.pv()[1]);
+
+        // The reply's subtree becomes the root and keeps its visits.
+        let after = pos.make_move(mv).make_move(reply);
+        let child = mcts.nodes[0].child_range().find(|&i| mcts.nodes[i].mv() == mv).unwrap();
+        let grandchild = mcts.nodes[child]
+            .child_range()
+            .find(|&i| mcts.nodes[i].mv() == reply)
+            .unwrap();
+        let visits = mcts.nodes[grandchild].visits;
+        assert!(visits > 0);
+        search(&mut mcts, &after, 1);
+        assert_eq!(mcts.nodes[0].visits, visits + 1);
+
+        // An unrelated position starts afresh, with the two playouts needed to pick a move.
+        search(&mut mcts, &Position::startpos(5), 1);
+        assert_eq!(mcts.nodes[0].visits, 2);
+
+        // So does the same position once the tree has been cleared.
+        mcts.clear();
+        search(&mut mcts, &Position::startpos(5), 1);
+        assert_eq!(mcts.nodes[0].visits, 2);
+    }
+
+    #[test]
+    fn full() {
+        let mut mcts = Mcts::new(16);
+        mcts.capacity = 1000;
+        let result = search(&mut mcts, &Position::default(), 2000);
+        assert_eq!(result.nodes, 2000);
+        assert_eq!(mcts.nodes[0].visits, 2000);
+        assert!(mcts.nodes.len() <= 1000);
+    }
+
+    #[test]
+    fn unexpanded_root() {
+        // Only the root's children fit, so they are visited without ever being expanded.
+        let mut mcts = Mcts::new(16);
+        mcts.capacity = 40;
+        let pos = Position::default();
+        let result = search(&mut mcts, &pos, 500);
+        let child = mcts.nodes[0]
+            .child_range()
+            .find(|&i| mcts.nodes[i].mv() == result.best_move)
+            .unwrap();
+        assert!(mcts.nodes[child].visits >= 2);
+        assert!(matches!(mcts.nodes[child].kind, Kind::Unexpanded));
+
+        // Even with no playouts to spare, the child becomes a root that can pick a move.
+        let after = pos.make_move(result.best_move);
+        let result = search(&mut mcts, &after, 0);
+        let mut moves = MoveList::new();
+        after.generate_moves(&mut moves);
+        assert!(moves.contains(&result.best_move));
+        assert_eq!(result.nodes, 2);
+    }
+
+    #[test]
+    fn game_over() {
+        let pos = Position::from_str("x5/x5/x5/1,1,1,1,1/2,2,2,2,x 2 5").unwrap();
+        let flag = AtomicBool::new(false);
+        assert!(
+            Mcts::new(1)
+                .search(&pos, &Limits::default(), Instant::now(), &flag, &flag)
+                .is_none()
+        );
+    }
+}
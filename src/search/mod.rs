@@ -0,0 +1,752 @@
+use crate::{
+    position::{MoveList, Network, Nnue, Position, Undo},
+    tei::send,
+    types::{Color, Move, PieceType},
+};
+use picker::{History, MovePicker};
+use smallvec::SmallVec;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use time::TimeManager;
+use tt::Bound;
+
+mod mcts;
+mod picker;
+mod time;
+mod tinue;
+mod tt;
+
+pub use mcts::Mcts;
+pub use tinue::solve_tinue;
+pub use tt::TranspositionTable;
+
+pub const MAX_PLY: usize = 128;
+pub const WIN: i32 = 30_000;
+pub const INFINITY: i32 = 31_000;
+/// Scores at least this large are road or flat wins found by the search.
+pub const WIN_IN_MAX_PLY: i32 = WIN - MAX_PLY as i32;
+
+pub type Pv = SmallVec<[Move; MAX_PLY]>;
+
+/// Threads add their node counts to the shared total in batches of this many nodes.
+const NODE_BATCH: u64 = 1024;
+
+/// Conditions under which a search stops. Without any limits the search keeps deepening until
+/// `MAX_PLY` or until it is stopped. `infinite` disables the clock and `movetime`. Clock times and
+/// increments are indexed by color.
+#[derive(Clone, Default)]
+pub struct Limits {
+    pub depth: Option<i32>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+    pub time: [Option<Duration>; Color::NUM],
+    pub inc: [Duration; Color::NUM],
+    pub movestogo: Option<u32>,
+    pub move_overhead: Duration,
+    pub infinite: bool,
+}
+
+pub struct SearchResult {
+    pub best_move: Move,
+    pub depth: i32,
+    pub nodes: u64,
+    /// Every principal variation of the last completed iteration, best first. Never empty.
+    pub lines: Vec<PvLine>,
+}
+
+impl SearchResult {
+    /// Score of the best line.
+    #[must_use]
+    pub fn score(&self) -> i32 {
+        self.lines[0].score
+    }
+
+    /// Principal variation of the best line, starting with `best_move`.
+    #[must_use]
+    pub fn pv(&self) -> &Pv {
+        &self.lines[0].pv
+    }
+}
+
+/// One of the lines reported in MultiPV mode: the best continuation after its first move.
+#[derive(Clone)]
+pub struct PvLine {
+    pub score: i32,
+    pub pv: Pv,
+}
+
+/// Helper threads skip some iterations so that they do not all search the same depth at once. The
+/// `i`-th helper skips depths in alternating runs of `SKIP_SIZE[i]`, shifted by `SKIP_PHASE[i]`.
+const SKIP_SIZE: [i32; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+const SKIP_PHASE: [i32; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
+
+/// A Lazy SMP search: every thread searches the same position independently, sharing only the
+/// transposition table. The main thread manages time and reports; the helpers just fill the table
+/// and are stopped once the main thread finishes.
+pub struct Searcher {
+    limits: Limits,
+    start: Instant,
+    /// When the clock started running: `start`, or the moment a ponder search was told that the
+    /// opponent played the expected move.
+    clock_start: Instant,
+    /// 0 for the main thread, 1 and up for helpers.
+    id: usize,
+    threads: usize,
+    multipv: usize,
+    nodes: u64,
+    /// Nodes searched by all threads, added to in batches.
+    total_nodes: Arc<AtomicU64>,
+    stopped: bool,
+    root_pv: Pv,
+    /// Root moves left out of the current search because an earlier MultiPV line starts with them.
+    root_excluded: MoveList,
+    killers: [[Option<Move>; 2]; MAX_PLY],
+    history: History,
+    time: Option<TimeManager>,
+    deadline: Option<Duration>,
+    stop: Arc<AtomicBool>,
+    /// Raised for as long as the search is pondering, during which it ignores the clock.
+    ponder: Arc<AtomicBool>,
+    pondering: bool,
+    /// Evaluates with a network instead of the handcrafted evaluation if set.
+    nnue: Option<Nnue>,
+    tt: Arc<TranspositionTable>,
+}
+
+impl Searcher {
+    /// Creates a single-threaded searcher whose clock started at `start`, normally when the `go`
+    /// command arrived. Raising `stop` ends the search as soon as the first iteration has
+    /// completed.
+    #[must_use]
+    pub fn new(limits: Limits, start: Instant, stop: Arc<AtomicBool>, tt: Arc<TranspositionTable>) -> Searcher {
+        Searcher {
+            limits,
+            start,
+            clock_start: start,
+            id: 0,
+            threads: 1,
+            multipv: 1,
+            nodes: 0,
+            total_nodes: Arc::default(),
+            stopped: false,
+            root_pv: Pv::new(),
+            root_excluded: MoveList::new(),
+            killers: [[None; 2]; MAX_PLY],
+            history: History::default(),
+            time: None,
+            deadline: None,
+            stop,
+            ponder: Arc::default(),
+            pondering: false,
+            nnue: None,
+            tt,
+        }
+    }
+
+    /// Sets the number of threads to search with, including the one calling `search`.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    /// Makes the search ponder for as long as `ponder` is raised: it runs as if there were no time
+    /// limits, and once the flag is lowered it carries on as a normal search whose clock starts then.
+    pub fn set_ponder(&mut self, ponder: Arc<AtomicBool>) {
+        self.ponder = ponder;
+    }
+
+    /// Sets how many principal variations the main thread searches and reports, each starting with
+    /// a different root move. Helpers always search a single line.
+    pub fn set_multipv(&mut self, multipv: usize) {
+        self.multipv = multipv.max(1);
+    }
+
+    /// Makes the search evaluate with `network`, or with the handcrafted evaluation if it is `None`.
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.nnue = network.map(Nnue::new);
+    }
+
+    /// Searches `pos` with iterative deepening, printing an `info` line after every completed
+    /// iteration. Returns `None` if the game is already over.
+    pub fn search(&mut self, pos: &Position) -> Option<SearchResult> {
+        if pos.terminal().is_some() {
+            return None;
+        }
+        self.tt.new_search();
+
+        if !self.limits.infinite {
+            self.time = TimeManager::new(&self.limits, pos);
+            let movetime = self
+                .limits
+                .movetime
+                .map(|time| time.saturating_sub(self.limits.move_overhead));
+            let hard = self.time.map(|time| time.hard());
+            self.deadline = [movetime, hard].into_iter().flatten().min();
+        }
+        self.pondering = self.ponder.load(Ordering::Relaxed);
+
+        let helpers_stop = Arc::new(AtomicBool::new(false));
+        let mut helpers: Vec<Searcher> = (1..self.threads)
+            .map(|id| self.helper(id, Arc::clone(&helpers_stop)))
+            .collect();
+
+        let result = thread::scope(|scope| {
+            for helper in &mut helpers {
+                scope.spawn(|| helper.iterate(pos));
+            }
+            let result = self.iterate(pos);
+            helpers_stop.store(true, Ordering::Relaxed);
+            result
+        });
+
+        result.map(|result| SearchResult {
+            nodes: self.total_nodes.load(Ordering::Relaxed),
+            ..result
+        })
+    }
+
+    fn helper(&self, id: usize, stop: Arc<AtomicBool>) -> Searcher {
+        Searcher {
+            id,
+            threads: self.threads,
+            total_nodes: Arc::clone(&self.total_nodes),
+            nnue: self.nnue.clone(),
+            ..Searcher::new(self.limits.clone(), self.start, stop, Arc::clone(&self.tt))
+        }
+    }
+
+    fn iterate(&mut self, pos: &Position) -> Option<SearchResult> {
+        let mut pos = pos.clone();
+        if let Some(nnue) = &mut self.nnue {
+            nnue.refresh(&pos);
+        }
+        let max_depth = self.limits.depth.unwrap_or(MAX_PLY as i32).clamp(1, MAX_PLY as i32 - 1);
+        let mut result: Option<SearchResult> = None;
+        let mut stability = 0;
+
+        let mut root_moves = MoveList::new();
+        pos.generate_moves(&mut root_moves);
+        let multipv = self.multipv.min(root_moves.len());
+
+        for depth in 1..=max_depth {
+            if self.id > 0 {
+                let i = (self.id - 1) % SKIP_SIZE.len();
+                if ((depth + SKIP_PHASE[i]) / SKIP_SIZE[i]) % 2 != 0 {
+                    continue;
+                }
+            }
+
+            // Each line searches the root moves that none of the lines before it start with. A line
+            // cut short by the stop is dropped, but the ones before it are kept.
+            let mut lines = Vec::with_capacity(multipv);
+            self.root_excluded.clear();
+            for _ in 0..multipv {
+                let mut pv = Pv::new();
+                let score = self.negamax(&mut pos, depth, 0, -INFINITY, INFINITY, &mut pv);
+                if self.stopped {
+                    break;
+                }
+                self.root_excluded.push(pv[0]);
+                lines.push(PvLine { score, pv });
+            }
+            self.root_excluded.clear();
+            let Some(best) = lines.first() else {
+                break;
+            };
+            let best_move = best.pv[0];
+
+            stability = match &result {
+                Some(previous) if previous.best_move == best_move => stability + 1,
+                _ => 0,
+            };
+
+            self.root_pv = best.pv.clone();
+            if self.id == 0 {
+                self.print_info(depth, &lines);
+            }
+            result = Some(SearchResult {
+                best_move,
+                depth,
+                nodes: self.total_nodes(),
+                lines,
+            });
+
+            if self.stopped
+                || (!self.is_pondering()
+                    && self
+                        .time
+                        .is_some_and(|time| !time.should_continue(self.clock_start.elapsed(), stability)))
+            {
+                break;
+            }
+        }
+
+        self.total_nodes.fetch_add(self.nodes % NODE_BATCH, Ordering::Relaxed);
+        result
+    }
+
+    /// Nodes searched by all threads so far, give or take the batches the helpers have not added
+    /// yet.
+    fn total_nodes(&self) -> u64 {
+        self.total_nodes.load(Ordering::Relaxed) + self.nodes % NODE_BATCH
+    }
+
+    fn negamax(&mut self, pos: &mut Position, depth: i32, ply: usize, mut alpha: i32, beta: i32, pv: &mut Pv) -> i32 {
+        pv.clear();
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(NODE_BATCH) {
+            self.total_nodes.fetch_add(NODE_BATCH, Ordering::Relaxed);
+        }
+
+        if ply > 0 {
+            if let Some(result) = pos.terminal() {
+                return match result.winner() {
+                    Some(winner) if winner == pos.stm() => WIN - ply as i32,
+                    Some(_) => -WIN + ply as i32,
+                    None => 0,
+                };
+            }
+            if self.should_stop() {
+                return 0;
+            }
+        }
+
+        if depth <= 0 || ply >= MAX_PLY - 1 {
+            return evaluate(self.nnue.as_ref(), pos);
+        }
+
+        // Completing a road is the best any move can do, so there is no need to search further.
+        if let Some(mv) = road_in_one(pos)
+            && (ply > 0 || !self.root_excluded.contains(&mv))
+        {
+            pv.push(mv);
+            return WIN - ply as i32 - 1;
+        }
+
+        // Scores from the table are only trusted outside the principal variation, which keeps the
+        // PV complete.
+        let pv_node = beta - alpha > 1;
+        let entry = self.tt.probe(pos.key(), ply);
+        if let Some(entry) = entry
+            && !pv_node
+            && entry.depth >= depth
+            && match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => entry.score >= beta,
+                Bound::Upper => entry.score <= alpha,
+            }
+        {
+            return entry.score;
+        }
+
+        let mut picker = MovePicker::new(entry.and_then(|entry| entry.mv), self.killers[ply]);
+        let original_alpha = alpha;
+        let mut best = -INFINITY;
+        let mut best_move = None;
+        let mut child_pv = Pv::new();
+        let mut tried = MoveList::new();
+        while let Some(mv) = picker.next(pos, &self.history) {
+            if ply == 0 && self.root_excluded.contains(&mv) {
+                continue;
+            }
+            let undo = do_move(self.nnue.as_mut(), pos, mv);
+            let mut score;
+            if tried.is_empty() {
+                score = -self.negamax(pos, depth - 1, ply + 1, -beta, -alpha, &mut child_pv);
+            } else {
+                score = -self.negamax(pos, depth - 1, ply + 1, -alpha - 1, -alpha, &mut child_pv);
+                if score > alpha && score < beta {
+                    score = -self.negamax(pos, depth - 1, ply + 1, -beta, -alpha, &mut child_pv);
+                }
+            }
+            undo_move(self.nnue.as_mut(), pos, mv, undo);
+
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best {
+                best = score;
+                if score > alpha {
+                    alpha = score;
+                    best_move = Some(mv);
+                    pv.clear();
+                    pv.push(mv);
+                    pv.extend_from_slice(&child_pv);
+                    if score >= beta {
+                        self.update_cutoff(mv, &tried, depth, ply);
+                        break;
+                    }
+                }
+            }
+            tried.push(mv);
+        }
+
+        let bound = if best >= beta {
+            Bound::Lower
+        } else if alpha > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        // A root search without some of its moves says nothing about the position itself.
+        if ply > 0 || self.root_excluded.is_empty() {
+            self.tt.store(pos.key(), ply, best_move, best, depth, bound);
+        }
+
+        best
+    }
+
+    /// Rewards the move that caused a beta cutoff and penalises the moves tried before it.
+    fn update_cutoff(&mut self, mv: Move, tried: &[Move], depth: i32, ply: usize) {
+        if self.killers[ply][0] != Some(mv) {
+            self.killers[ply] = [Some(mv), self.killers[ply][0]];
+        }
+
+        let bonus = depth * depth;
+        self.history.update(mv, bonus);
+        for &mv in tried {
+            self.history.update(mv, -bonus);
+        }
+    }
+
+    /// Checks the stop flag and the node and time limits. The first iteration always runs to
+    /// completion so that there is a move to play.
+    fn should_stop(&mut self) -> bool {
+        if self.id == 0 && self.root_pv.is_empty() {
+            return false;
+        }
+
+        if self.stop.load(Ordering::Relaxed)
+            || self.limits.nodes.is_some_and(|nodes| self.total_nodes() >= nodes)
+            || (self.nodes.is_multiple_of(1024)
+                && !self.is_pondering()
+                && self.deadline.is_some_and(|time| self.clock_start.elapsed() >= time))
+        {
+            self.stopped = true;
+        }
+
+        self.stopped
+    }
+
+    /// Whether the search is still pondering. The clock starts once it stops.
+    fn is_pondering(&mut self) -> bool {
+        if self.pondering && !self.ponder.load(Ordering::Relaxed) {
+            self.pondering = false;
+            self.clock_start = Instant::now();
+        }
+        self.pondering
+    }
+
+    /// Prints one `info` line per principal variation of the iteration that just completed.
+    fn print_info(&self, depth: i32, lines: &[PvLine]) {
+        let elapsed = self.start.elapsed();
+        let nodes = self.total_nodes();
+        let nps = (nodes as f64 / elapsed.as_secs_f64().max(1e-6)) as u64;
+        for (i, line) in lines.iter().enumerate() {
+            let pv: Vec<String> = line.pv.iter().map(|mv| mv.to_string()).collect();
+            send!(
+                "info depth {depth} multipv {} score {} nodes {} nps {nps} hashfull {} time {} pv {}",
+                i + 1,
+                format_score(line.score),
+                nodes,
+                self.tt.hashfull(),
+                elapsed.as_millis(),
+                pv.join(" ")
+            );
+        }
+    }
+}
+
+/// Formats a score for an `info` line: `cp` for evaluations, and `mate` followed by the number of
+/// the side to move's own moves until the game ends for wins, negated for losses.
+fn format_score(score: i32) -> String {
+    if score >= WIN_IN_MAX_PLY {
+        format!("mate {}", (WIN - score + 1) / 2)
+    } else if score <= -WIN_IN_MAX_PLY {
+        format!("mate -{}", (WIN + score) / 2)
+    } else {
+        format!("cp {score}")
+    }
+}
+
+/// Plays `mv`, keeping the accumulators of `nnue` up to date when searching with a network.
+fn do_move(nnue: Option<&mut Nnue>, pos: &mut Position, mv: Move) -> Undo {
+    match nnue {
+        Some(nnue) => nnue.do_move(pos, mv),
+        None => pos.do_move(mv),
+    }
+}
+
+fn undo_move(nnue: Option<&mut Nnue>, pos: &mut Position, mv: Move, undo: Undo) {
+    match nnue {
+        Some(nnue) => nnue.undo_move(pos, mv, undo),
+        None => pos.undo_move(mv, undo),
+    }
+}
+
+/// The static evaluation of `pos`, with the network when searching with one.
+fn evaluate(nnue: Option<&Nnue>, pos: &Position) -> i32 {
+    nnue.map_or_else(|| pos.evaluate(), |nnue| nnue.evaluate(pos))
+}
+
+/// A placement that completes a road for the side to move, if there is one.
+fn road_in_one(pos: &Position) -> Option<Move> {
+    if pos.ply() < 2 {
+        return None;
+    }
+    let stm = pos.stm();
+    let pt = if pos.remaining_stones(stm) > 0 {
+        PieceType::Flat
+    } else if pos.remaining_caps(stm) > 0 {
+        PieceType::Cap
+    } else {
+        return None;
+    };
+    let sq = pos.road_threats(stm).lsb();
+    sq.is_some().then(|| Move::place(pt, sq))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn searcher(limits: Limits) -> Searcher {
+        Searcher::new(
+            limits,
+            Instant::now(),
+            Arc::default(),
+            Arc::new(TranspositionTable::new(1)),
+        )
+    }
+
+    fn search(tps: &str, depth: i32) -> SearchResult {
+        let pos = Position::from_str(tps).unwrap();
+        let limits = Limits {
+            depth: Some(depth),
+            ..Limits::default()
+        };
+        searcher(limits).search(&pos).unwrap()
+    }
+
+    fn minimax(pos: &Position, depth: i32, ply: usize, evaluate: &dyn Fn(&Position) -> i32) -> i32 {
+        if ply > 0
+            && let Some(result) = pos.terminal()
+        {
+            return match result.winner() {
+                Some(winner) if winner == pos.stm() => WIN - ply as i32,
+                Some(_) => -WIN + ply as i32,
+                None => 0,
+            };
+        }
+        if depth == 0 {
+            return evaluate(pos);
+        }
+
+        let mut moves = MoveList::new();
+        pos.generate_moves(&mut moves);
+        moves
+            .iter()
+            .map(|&mv| -minimax(&pos.make_move(mv), depth - 1, ply + 1, evaluate))
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn matches_minimax() {
+        let cases = [
+            "x3/x3/x3 1 1",
+            "2,1,x/x,21S,x/1,x,2 2 4",
+            "x4/x,2,1,x/x,12,x2/1,x2,2 1 5",
+            "x,1,2,x2/x,21C,12S,x,1/x5/2,x,12C,x2/x4,1 1 9",
+        ];
+        for tps in cases {
+            let pos = Position::from_str(tps).unwrap();
+            for depth in 1..=3 {
+                assert_eq!(
+                    search(tps, depth).score(),
+                    minimax(&pos, depth, 0, &Position::evaluate),
+                    "{tps} depth {depth}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn network() {
+        let network = Arc::new(Network::embedded());
+        let pos = Position::from_str("x4/x,2,1,x/x,12,x2/1,x2,2 1 5").unwrap();
+        for depth in 1..=3 {
+            let mut searcher = searcher(Limits {
+                depth: Some(depth),
+                ..Limits::default()
+            });
+            searcher.set_network(Some(Arc::clone(&network)));
+            let expected = minimax(&pos, depth, 0, &|pos| network.evaluate(pos));
+            assert_eq!(searcher.search(&pos).unwrap().score(), expected, "depth {depth}");
+        }
+    }
+
+    /// Each line scores as well as the best root move not starting an earlier line.
+    #[test]
+    fn multipv() {
+        let search = |pos: &Position, depth, multipv| {
+            let mut searcher = searcher(Limits {
+                depth: Some(depth),
+                ..Limits::default()
+            });
+            searcher.set_multipv(multipv);
+            searcher.search(pos).unwrap()
+        };
+
+        let pos = Position::from_str("x4/x,2,1,x/x,12,x2/1,x2,2 1 5").unwrap();
+        let mut moves = MoveList::new();
+        pos.generate_moves(&mut moves);
+        let mut scores: Vec<i32> = moves
+            .iter()
+            .map(|&mv| -minimax(&pos.make_move(mv), 2, 1, &Position::evaluate))
+            .collect();
+        scores.sort_unstable_by(|a, b| b.cmp(a));
+
+        let result = search(&pos, 3, 4);
+        assert_eq!(result.lines.len(), 4);
+        assert_eq!(result.score(), result.lines[0].score);
+        assert!(result.best_move == result.lines[0].pv[0]);
+        for (i, line) in result.lines.iter().enumerate() {
+            assert_eq!(line.score, scores[i], "line {}", i + 1);
+            assert!(result.lines[..i].iter().all(|other| other.pv[0] != line.pv[0]));
+        }
+
+        // There are never more lines than legal moves.
+        let result = search(&Position::from_str("x3/x3/x3 1 1").unwrap(), 2, 100);
+        assert_eq!(result.lines.len(), 9);
+    }
+
+    #[test]
+    fn scores() {
+        assert_eq!(format_score(35), "cp 35");
+        assert_eq!(format_score(-WIN_IN_MAX_PLY + 1), "cp -29871");
+        assert_eq!(format_score(WIN - 1), "mate 1");
+        assert_eq!(format_score(WIN - 3), "mate 2");
+        assert_eq!(format_score(-WIN + 2), "mate -1");
+        assert_eq!(format_score(-WIN + 4), "mate -2");
+    }
+
+    #[test]
+    fn road_in_one() {
+        let result = search("x5/x5/x5/1,1,1,1,x/2,2,2,2,x 1 5", 3);
+        assert_eq!(result.best_move.to_string(), "e2");
+        assert_eq!(result.score(), WIN - 1);
+        assert_eq!(result.pv().len(), 1);
+    }
+
+    #[test]
+    fn blocks_road() {
+        let tps = "x5/x5/x5/1,1,1,1,x/2,2,x3 2 4";
+        let result = search(tps, 2);
+        assert!(result.score() > -WIN + MAX_PLY as i32);
+
+        let pos = Position::from_str(tps).unwrap().make_move(result.best_move);
+        let mut moves = MoveList::new();
+        pos.generate_moves(&mut moves);
+        assert!(moves.iter().all(|&mv| pos.make_move(mv).terminal().is_none()));
+    }
+
+    #[test]
+    fn node_limit() {
+        let limits = Limits {
+            nodes: Some(5000),
+            ..Limits::default()
+        };
+        let result = searcher(limits).search(&Position::default()).unwrap();
+        assert!(result.depth >= 1);
+        assert!(result.nodes < 10000);
+    }
+
+    #[test]
+    fn clock() {
+        let limits = Limits {
+            time: [Some(Duration::from_millis(2_000)); 2],
+            move_overhead: Duration::from_millis(100),
+            ..Limits::default()
+        };
+        let pos = Position::default();
+        let tm = TimeManager::new(&limits, &pos).unwrap();
+        let start = Instant::now();
+        let mut searcher = searcher(limits);
+        searcher.search(&pos).unwrap();
+        let elapsed = start.elapsed();
+
+        assert_eq!(searcher.deadline, Some(tm.hard()));
+        // No iteration stops the search before 0.7 of the soft limit. The margin past the hard limit
+        // covers the nodes between deadline checks and a busy machine.
+        assert!(elapsed >= tm.soft().mul_f64(0.7), "{elapsed:?}");
+        assert!(elapsed < tm.hard() + Duration::from_millis(250), "{elapsed:?}");
+    }
+
+    #[test]
+    fn ponder() {
+        let limits = Limits {
+            movetime: Some(Duration::from_millis(1)),
+            ..Limits::default()
+        };
+        let ponder = Arc::new(AtomicBool::new(true));
+        let mut searcher = searcher(limits);
+        searcher.set_ponder(Arc::clone(&ponder));
+
+        thread::scope(|scope| {
+            let search = scope.spawn(|| searcher.search(&Position::default()).unwrap());
+            // The movetime only starts counting after the ponderhit.
+            thread::sleep(Duration::from_millis(100));
+            assert!(!search.is_finished());
+            ponder.store(false, Ordering::Relaxed);
+            let hit = Instant::now();
+            while !search.is_finished() && hit.elapsed() < Duration::from_secs(10) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            assert!(search.is_finished());
+        });
+    }
+
+    #[test]
+    fn stopped() {
+        let mut searcher = searcher(Limits::default());
+        searcher.stop.store(true, Ordering::Relaxed);
+        let result = searcher.search(&Position::default()).unwrap();
+        assert_eq!(result.depth, 1);
+    }
+
+    #[test]
+    fn threads() {
+        let search = |tps: &str| {
+            let mut searcher = searcher(Limits {
+                depth: Some(4),
+                ..Limits::default()
+            });
+            searcher.set_threads(4);
+            let result = searcher.search(&Position::from_str(tps).unwrap()).unwrap();
+            (result, searcher.nodes)
+        };
+
+        let (result, _) = search("x5/x5/x5/1,1,1,1,x/2,2,2,2,x 1 5");
+        assert_eq!(result.best_move.to_string(), "e2");
+        assert_eq!(result.score(), WIN - 1);
+
+        // The total includes the helpers' nodes.
+        let (result, main_nodes) = search("x,1,2,x2/x,21C,12S,x,1/x5/2,x,12C,x2/x4,1 1 9");
+        assert_eq!(result.depth, 4);
+        assert!(result.nodes >= main_nodes);
+    }
+
+    #[test]
+    fn game_over() {
+        let pos = Position::from_str("x5/x5/x5/1,1,1,1,1/2,2,2,2,x 2 5").unwrap();
+        assert!(searcher(Limits::default()).search(&pos).is_none());
+    }
+}
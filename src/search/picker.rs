@@ -0,0 +1,201 @@
+use crate::{
+    position::{MoveList, Position},
+    types::{Move, PieceType},
+};
+use smallvec::SmallVec;
+
+/// Largest magnitude a history score can reach.
+const HISTORY_MAX: i32 = 16_384;
+
+/// How often each move caused a beta cutoff recently, indexed by `Move::raw()`.
+pub struct History(Box<[i32]>);
+
+impl Default for History {
+    fn default() -> History {
+        History(vec![0; 1 << 16].into_boxed_slice())
+    }
+}
+
+impl History {
+    #[must_use]
+    pub fn get(&self, mv: Move) -> i32 {
+        self.0[mv.raw() as usize]
+    }
+
+    /// Adds `bonus`, which may be negative. Scores shrink towards zero as they grow, so they stay
+    /// within `HISTORY_MAX` and recent cutoffs count for more than old ones.
+    pub fn update(&mut self, mv: Move, bonus: i32) {
+        let bonus = bonus.clamp(-HISTORY_MAX, HISTORY_MAX);
+        let entry = &mut self.0[mv.raw() as usize];
+        *entry += bonus - *entry * bonus.abs() / HISTORY_MAX;
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+enum Stage {
+    TtMove,
+    RoadWins,
+    RoadBlocks,
+    Killers,
+    Placements,
+    Spreads,
+    Done,
+}
+
+impl Stage {
+    fn next(self) -> Stage {
+        match self {
+            Stage::TtMove => Stage::RoadWins,
+            Stage::RoadWins => Stage::RoadBlocks,
+            Stage::RoadBlocks => Stage::Killers,
+            Stage::Killers => Stage::Placements,
+            Stage::Placements => Stage::Spreads,
+            Stage::Spreads | Stage::Done => Stage::Done,
+        }
+    }
+}
+
+/// Yields the legal moves of a position in the order the search should try them: the table move,
+/// placements that complete a road, placements on the squares where the opponent would complete
+/// one, killer moves, and then the remaining placements and spreads, each by history score.
+///
+/// Each stage generates its moves only once the previous one has run out, so a cutoff early on
+/// saves generating the rest. Moves that complete a road by spreading come with the other spreads.
+pub struct MovePicker {
+    stage: Stage,
+    killers: [Option<Move>; 2],
+    moves: MoveList,
+    index: usize,
+    /// Moves yielded before the full generation stages, which those stages skip.
+    yielded: SmallVec<[Move; 32]>,
+}
+
+impl MovePicker {
+    /// Creates a picker that starts with `tt_move`, if it is legal.
+    #[must_use]
+    pub fn new(tt_move: Option<Move>, killers: [Option<Move>; 2]) -> MovePicker {
+        MovePicker {
+            stage: Stage::TtMove,
+            killers,
+            moves: tt_move.into_iter().collect(),
+            index: 0,
+            yielded: SmallVec::new(),
+        }
+    }
+
+    /// The next move to try in `pos`, which must be the same position every time.
+    pub fn next(&mut self, pos: &Position, history: &History) -> Option<Move> {
+        loop {
+            while let Some(&mv) = self.moves.get(self.index) {
+                self.index += 1;
+                // The table move may come from a different position with the same key.
+                if self.yielded.contains(&mv) || (self.stage == Stage::TtMove && !pos.is_legal(mv)) {
+                    continue;
+                }
+                if self.stage < Stage::Placements {
+                    self.yielded.push(mv);
+                }
+                return Some(mv);
+            }
+
+            if self.stage == Stage::Done {
+                return None;
+            }
+            self.stage = self.stage.next();
+            self.generate(pos, history);
+        }
+    }
+
+    fn generate(&mut self, pos: &Position, history: &History) {
+        self.moves.clear();
+        self.index = 0;
+
+        match self.stage {
+            Stage::TtMove | Stage::Done => {}
+            // Opening placements are of the opponent's flats, so they neither win nor block.
+            Stage::RoadWins | Stage::RoadBlocks if pos.ply() < 2 => {}
+            Stage::RoadWins => {
+                for sq in pos.road_threats(pos.stm()) {
+                    self.push_legal(pos, [Move::place(PieceType::Flat, sq), Move::place(PieceType::Cap, sq)]);
+                }
+            }
+            Stage::RoadBlocks => {
+                for sq in pos.road_threats(!pos.stm()) {
+                    let pts = [PieceType::Wall, PieceType::Cap, PieceType::Flat];
+                    self.push_legal(pos, pts.map(|pt| Move::place(pt, sq)));
+                }
+            }
+            Stage::Killers => self.push_legal(pos, self.killers.into_iter().flatten()),
+            Stage::Placements => {
+                pos.generate_placements(&mut self.moves);
+                self.moves.sort_by_cached_key(|&mv| -history.get(mv));
+            }
+            Stage::Spreads => {
+                pos.generate_spreads(&mut self.moves);
+                self.moves.sort_by_cached_key(|&mv| -history.get(mv));
+            }
+        }
+    }
+
+    fn push_legal(&mut self, pos: &Position, moves: impl IntoIterator<Item = Move>) {
+        self.moves.extend(moves.into_iter().filter(|&mv| pos.is_legal(mv)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Square;
+    use std::{collections::HashSet, str::FromStr};
+
+    const POSITIONS: [&str; 5] = [
+        "x6/x6/x6/x6/x6/x6 1 1",
+        "x6/x6/x6/x6/x6/x6 2 1",
+        "x5/x5/x5/1,1,1,1,x/2,2,2,x,2 1 5",
+        "x6/x4,1S,x/x2,21111S,1C,22122C,x/x6/x6/x6 2 11",
+        "x,1,x,2,x2/1,2,12,1,2,x/x,21,2C,1S,1,x/2,1,1C,2,21,x/x,2,1,2,1,x/x2,2,x3 2 17",
+    ];
+
+    fn picked(pos: &Position, tt_move: Option<Move>, killers: [Option<Move>; 2]) -> Vec<Move> {
+        let history = History::default();
+        let mut picker = MovePicker::new(tt_move, killers);
+        std::iter::from_fn(|| picker.next(pos, &history)).collect()
+    }
+
+    #[test]
+    fn yields_legal_moves_once() {
+        for tps in POSITIONS {
+            let pos = Position::from_str(tps).unwrap();
+            let mut moves = MoveList::new();
+            pos.generate_moves(&mut moves);
+            let killers = [moves.last().copied(), None];
+
+            let picked = picked(&pos, moves.get(moves.len() / 2).copied(), killers);
+            assert_eq!(picked.len(), moves.len(), "{tps}");
+            let unique: HashSet<Move> = picked.iter().copied().collect();
+            assert_eq!(unique.len(), picked.len(), "{tps}");
+            assert!(moves.iter().all(|mv| unique.contains(mv)), "{tps}");
+        }
+    }
+
+    #[test]
+    fn ordering() {
+        let pos = Position::from_str(POSITIONS[2]).unwrap();
+        let mut moves = MoveList::new();
+        pos.generate_moves(&mut moves);
+        let tt_move = moves[moves.len() - 1];
+
+        // The table move comes first, then the road win on e2.
+        let picked = picked(&pos, Some(tt_move), [None; 2]);
+        assert!(picked[0] == tt_move);
+        assert!(picked[1] == Move::place(PieceType::Flat, Square::from_str("e2").unwrap()));
+    }
+
+    #[test]
+    fn illegal_tt_move() {
+        let pos = Position::from_str(POSITIONS[3]).unwrap();
+        let occupied = Move::place(PieceType::Flat, Square::from_str("c4").unwrap());
+        assert!(!pos.is_legal(occupied));
+        assert!(!picked(&pos, Some(occupied), [Some(occupied), None]).contains(&occupied));
+    }
+}
@@ -0,0 +1,122 @@
+use super::Limits;
+use crate::position::Position;
+use std::time::Duration;
+
+/// Time budget for one move when playing on a clock.
+///
+/// No new iteration is started once the soft limit has passed, scaled up while the best move keeps
+/// changing and down once it has settled. The search is aborted outright at the hard limit.
+#[derive(Clone, Copy, Debug)]
+pub struct TimeManager {
+    soft: Duration,
+    hard: Duration,
+}
+
+impl TimeManager {
+    /// Allocates time from the side to move's clock. Returns `None` if there is no clock to manage.
+    #[must_use]
+    pub fn new(limits: &Limits, pos: &Position) -> Option<TimeManager> {
+        let c = pos.stm().to_index();
+        let time = limits.time[c]?;
+        let inc = limits.inc[c];
+
+        let available = time.saturating_sub(limits.move_overhead);
+        let moves_left = limits.movestogo.unwrap_or_else(|| estimate_moves_left(pos)).max(1);
+        let base = available / moves_left + inc * 3 / 4;
+
+        let hard = (base * 4).min(available * 3 / 4);
+        let soft = base.min(hard);
+        Some(TimeManager { soft, hard })
+    }
+
+    #[must_use]
+    pub fn soft(&self) -> Duration {
+        self.soft
+    }
+
+    #[must_use]
+    pub fn hard(&self) -> Duration {
+        self.hard
+    }
+
+    /// Whether another iteration should be started after `elapsed`, where `stability` is the number
+    /// of consecutive iterations that returned the same best move.
+    #[must_use]
+    pub fn should_continue(&self, elapsed: Duration, stability: u32) -> bool {
+        let scale = match stability {
+            0 => 2.0,
+            1 => 1.4,
+            2 => 1.1,
+            3..=5 => 0.9,
+            _ => 0.7,
+        };
+        elapsed < self.soft.mul_f64(scale).min(self.hard)
+    }
+}
+
+/// Estimates how many more moves the side to move will make. A game is over at the latest when a
+/// player's reserves run out, but spreads make it last longer than the placements alone would
+/// suggest, so the estimate tapers off slowly as the reserves shrink.
+fn estimate_moves_left(pos: &Position) -> u32 {
+    let reserves = pos.remaining_stones(pos.stm()) as u32 + pos.remaining_caps(pos.stm()) as u32;
+    reserves * 2 / 3 + 8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn clock(time: u64, inc: u64) -> Limits {
+        Limits {
+            time: [Some(Duration::from_millis(time)); 2],
+            inc: [Duration::from_millis(inc); 2],
+            ..Limits::default()
+        }
+    }
+
+    #[test]
+    fn allocation() {
+        let pos = Position::default();
+        let tm = TimeManager::new(&clock(60_000, 0), &pos).unwrap();
+        assert!(tm.soft < tm.hard);
+        assert!(tm.hard < Duration::from_secs(20));
+        assert!(tm.soft > Duration::from_secs(1));
+
+        let with_inc = TimeManager::new(&clock(60_000, 2_000), &pos).unwrap();
+        assert!(with_inc.soft > tm.soft);
+
+        let tps = "2,x,2,111S,2,12/2,122S,2122,1S,x,1/x,111,1,11S,x2/21122112C,x,212S,2S,2,1212S/1,112S,21221S,2S,x2/21,222,x,12S,x2 2 30";
+        let late = TimeManager::new(&clock(60_000, 0), &Position::from_str(tps).unwrap()).unwrap();
+        assert!(late.soft > tm.soft);
+
+        assert!(TimeManager::new(&Limits::default(), &pos).is_none());
+    }
+
+    #[test]
+    fn low_time() {
+        let pos = Position::default();
+        let limits = Limits {
+            move_overhead: Duration::from_millis(50),
+            ..clock(40, 0)
+        };
+        let tm = TimeManager::new(&limits, &pos).unwrap();
+        assert_eq!(tm.hard, Duration::ZERO);
+
+        let limits = Limits {
+            movestogo: Some(1),
+            ..clock(1_000, 0)
+        };
+        let tm = TimeManager::new(&limits, &pos).unwrap();
+        assert_eq!(tm.hard, Duration::from_millis(750));
+    }
+
+    #[test]
+    fn stability() {
+        let tm = TimeManager::new(&clock(60_000, 0), &Position::default()).unwrap();
+        let elapsed = tm.soft.mul_f64(1.2);
+        assert!(tm.should_continue(elapsed, 0));
+        assert!(!tm.should_continue(elapsed, 10));
+        assert!(!tm.should_continue(tm.hard, 0));
+    }
+}
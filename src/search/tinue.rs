@@ -0,0 +1,237 @@
+use super::{Pv, road_in_one};
+use crate::{
+    position::{EndReason, MoveList, Position},
+    tei::send,
+    types::Move,
+};
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Instant,
+};
+
+/// Looks for a forced road win (tinue) for the side to move within `max_depth` plies, trying
+/// shorter wins first and printing an `info` line after each depth. Returns the winning line
+/// against the longest defence, whose length is the number of plies to the road, or `None` if
+/// there is no tinue within `max_depth` plies or `stop` was raised first.
+///
+/// Every move of the attacker but the last must threaten to complete a road on the next move, so
+/// wins that need a quiet move along the way are not found.
+pub fn solve_tinue(pos: &Position, max_depth: usize, stop: &AtomicBool) -> Option<Pv> {
+    let mut solver = TinueSolver {
+        stop,
+        start: Instant::now(),
+        nodes: 0,
+        stopped: false,
+        refuted: HashMap::new(),
+    };
+    if pos.terminal().is_some() {
+        return None;
+    }
+
+    let mut pos = pos.clone();
+    let mut line = Pv::new();
+    for depth in (1..=max_depth).step_by(2) {
+        let found = solver.attack(&mut pos, depth, &mut line);
+        if solver.stopped {
+            return None;
+        }
+
+        send!(
+            "info depth {depth} nodes {} time {}",
+            solver.nodes,
+            solver.start.elapsed().as_millis()
+        );
+        if found {
+            return Some(line);
+        }
+    }
+    None
+}
+
+struct TinueSolver<'a> {
+    stop: &'a AtomicBool,
+    start: Instant,
+    nodes: u64,
+    stopped: bool,
+    /// The deepest search that failed to find a tinue from each position, by Zobrist key.
+    refuted: HashMap<u64, usize>,
+}
+
+impl TinueSolver<'_> {
+    /// Whether the side to move can force a road within `depth` plies, leaving the line in `line`.
+    fn attack(&mut self, pos: &mut Position, depth: usize, line: &mut Pv) -> bool {
+        line.clear();
+        self.nodes += 1;
+
+        if let Some(mv) = road_win(pos) {
+            line.push(mv);
+            return true;
+        }
+        if depth < 3 || self.should_stop() || self.refuted.get(&pos.key()).is_some_and(|&refuted| refuted >= depth) {
+            return false;
+        }
+
+        let mut moves = MoveList::new();
+        pos.generate_moves(&mut moves);
+
+        let mut defence = Pv::new();
+        for mv in moves {
+            let undo = pos.do_move(mv);
+            let wins = pos.terminal().is_none() && threatens(pos) && self.defend(pos, depth - 1, &mut defence);
+            pos.undo_move(mv, undo);
+
+            if wins {
+                line.push(mv);
+                line.extend_from_slice(&defence);
+                return true;
+            }
+            if self.stopped {
+                return false;
+            }
+        }
+
+        self.refuted.insert(pos.key(), depth);
+        false
+    }
+
+    /// Whether every move of the side to move loses to a road within `depth` plies, leaving the
+    /// longest defence in `line`.
+    fn defend(&mut self, pos: &mut Position, depth: usize, line: &mut Pv) -> bool {
+        line.clear();
+        self.nodes += 1;
+
+        let defender = pos.stm();
+        let mut moves = MoveList::new();
+        pos.generate_moves(&mut moves);
+
+        let mut attack = Pv::new();
+        for mv in moves {
+            let undo = pos.do_move(mv);
+            let refuted = match pos.terminal() {
+                Some(result) => {
+                    attack.clear();
+                    result.winner() == Some(!defender) && result.reason() == EndReason::Road
+                }
+                None => self.attack(pos, depth - 1, &mut attack),
+            };
+            pos.undo_move(mv, undo);
+
+            if !refuted {
+                return false;
+            }
+            if line.is_empty() || attack.len() + 1 > line.len() {
+                line.clear();
+                line.push(mv);
+                line.extend_from_slice(&attack);
+            }
+        }
+        true
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if self.stop.load(Ordering::Relaxed) {
+            self.stopped = true;
+        }
+        self.stopped
+    }
+}
+
+/// Whether the side that just moved could complete a road if it were to move again.
+fn threatens(pos: &mut Position) -> bool {
+    pos.do_null_move();
+    let threat = road_win(pos).is_some();
+    pos.undo_null_move();
+    threat
+}
+
+/// A move that completes a road for the side to move, if there is one.
+fn road_win(pos: &Position) -> Option<Move> {
+    if let Some(mv) = road_in_one(pos) {
+        return Some(mv);
+    }
+
+    let mut moves = MoveList::new();
+    pos.winning_moves(&mut moves);
+    moves.into_iter().find(|&mv| {
+        pos.make_move(mv)
+            .terminal()
+            .is_some_and(|result| result.reason() == EndReason::Road)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::{Limits, Searcher, TranspositionTable, WIN};
+    use std::{str::FromStr, sync::Arc};
+
+    /// Known positions with the length of the shortest tinue for the side to move, if any.
+    const CASES: [(&str, Option<usize>); 8] = [
+        ("x5/x5/x5/1,1,1,1,x/2,2,2,x,2 1 5", Some(1)),
+        ("2,2,x,1,x/2,2,x,1,x/2,2,x,1,x/1,1,1,x2/x5 1 7", Some(3)),
+        (
+            "x6/2C,1,1,1,1,1/2,x,111121S,x3/2,x,11,x,1,x/2,1C,12,2,2,2/x,112,x4 2 22",
+            Some(3),
+        ),
+        ("2,2,x,1,x/2,2,x2,x/2,2,x,1,x/1,1,1,x2/x5 1 6", Some(5)),
+        ("x5/x5/x5/1,1,1,x2/2,2,x3 1 4", None),
+        // P1 has a double threat, but P2 completes a road first.
+        ("x3,1,x/x3,1,x/2,2,x,1,x/1,1,1,x2/2,2,2,2,x 1 7", None),
+        ("x6/x4,1S,x/x2,21111S,1C,22122C,x/x6/x6/x6 2 11", None),
+        (
+            "2,x,2,111S,2,12/2,122S,2122,1S,x,1/x,111,1,11S,x2/21122112C,x,212S,2S,2,1212S/1,112S,21221S,2S,x2/21,222,x,12S,x2 2 30",
+            None,
+        ),
+    ];
+
+    #[test]
+    fn regression() {
+        for (tps, expected) in CASES {
+            let pos = Position::from_str(tps).unwrap();
+            let line = solve_tinue(&pos, 7, &AtomicBool::new(false));
+            assert_eq!(line.as_ref().map(|line| line.len()), expected, "{tps}");
+
+            // The line must be legal and end in a road for the attacker.
+            if let Some(line) = line {
+                let mut game = pos.clone();
+                for &mv in &line {
+                    assert!(game.terminal().is_none(), "{tps}");
+                    game = game.make_move(mv);
+                }
+                let result = game.terminal().unwrap();
+                assert_eq!(result.winner(), Some(pos.stm()), "{tps}");
+                assert_eq!(result.reason(), EndReason::Road, "{tps}");
+            }
+        }
+    }
+
+    /// A full-width search agrees that the tinues are forced wins of at most their length.
+    #[test]
+    fn matches_search() {
+        for (tps, expected) in CASES.iter().filter_map(|&(tps, length)| Some((tps, length?))) {
+            let pos = Position::from_str(tps).unwrap();
+            let limits = Limits {
+                depth: Some(expected as i32),
+                ..Limits::default()
+            };
+            let tt = Arc::new(TranspositionTable::new(16));
+            let result = Searcher::new(limits, Instant::now(), Arc::default(), tt)
+                .search(&pos)
+                .unwrap();
+            assert!(result.score() >= WIN - expected as i32, "{tps}");
+        }
+    }
+
+    #[test]
+    fn stopped() {
+        let pos = Position::from_str(CASES[3].0).unwrap();
+        assert!(solve_tinue(&pos, 7, &AtomicBool::new(true)).is_none());
+    }
+
+    #[test]
+    fn game_over() {
+        let pos = Position::from_str("x5/x5/x5/1,1,1,1,1/2,2,2,2,x 2 5").unwrap();
+        assert!(solve_tinue(&pos, 7, &AtomicBool::new(false)).is_none());
+    }
+}
@@ -0,0 +1,341 @@
+use super::WIN_IN_MAX_PLY;
+use crate::types::Move;
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum Bound {
+    /// The score is at most the stored value (the node failed low).
+    Upper = 1,
+    /// The score is at least the stored value (the node failed high).
+    Lower = 2,
+    Exact = 3,
+}
+
+impl Bound {
+    const fn from_index(index: u8) -> Bound {
+        debug_assert!(index >= 1 && index <= 3);
+        unsafe { std::mem::transmute(index) }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct TtEntry {
+    pub mv: Option<Move>,
+    pub score: i32,
+    pub depth: i32,
+    pub bound: Bound,
+}
+
+const BUCKET_SIZE: usize = 4;
+const GENERATIONS: u8 = 64;
+
+/// Salt that separates perft counts from search results, and counts at different depths from each
+/// other.
+const PERFT_SALT: u64 = 0x7065_7266_7420_5454;
+
+/// Slots hold 64 bits of data:
+///
+/// | bits  | search entry    | perft entry  |
+/// |-------|-----------------|--------------|
+/// | 0-7   | depth           | depth        |
+/// | 8-9   | bound           | exact        |
+/// | 10-15 | generation      | generation   |
+/// | 16-31 | move (`raw()`)  | count        |
+/// | 32-47 | score           | count        |
+/// | 48-63 | unused          | count        |
+///
+/// The key is stored xored with the data so that a slot torn by two threads writing at once fails
+/// the key check rather than returning another position's data. An all-zero slot is empty.
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+impl Slot {
+    fn load(&self) -> (u64, u64) {
+        let data = self.data.load(Ordering::Relaxed);
+        (self.key.load(Ordering::Relaxed) ^ data, data)
+    }
+
+    fn store(&self, key: u64, data: u64) {
+        self.key.store(key ^ data, Ordering::Relaxed);
+        self.data.store(data, Ordering::Relaxed);
+    }
+}
+
+fn depth_of(data: u64) -> u8 {
+    data as u8
+}
+
+fn bound_of(data: u64) -> u8 {
+    (data >> 8) as u8 & 0x3
+}
+
+fn generation_of(data: u64) -> u8 {
+    (data >> 10) as u8 & 0x3F
+}
+
+#[derive(Default)]
+#[repr(align(64))]
+struct Bucket([Slot; BUCKET_SIZE]);
+
+/// A hash table of search results shared by all search threads without locking.
+///
+/// Each position maps to a bucket of four slots. A new result replaces the same position's entry,
+/// an empty slot, or otherwise the slot with the least valuable entry, where entries lose value
+/// with every search they survive.
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+    generation: AtomicU8,
+}
+
+impl TranspositionTable {
+    #[must_use]
+    pub fn new(mb: usize) -> TranspositionTable {
+        let len = (mb * 1024 * 1024 / size_of::<Bucket>()).max(1);
+        TranspositionTable {
+            buckets: (0..len).map(|_| Bucket::default()).collect(),
+            generation: AtomicU8::new(0),
+        }
+    }
+
+    pub fn clear(&self) {
+        for slot in self.buckets.iter().flat_map(|bucket| &bucket.0) {
+            slot.store(0, 0);
+        }
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    /// Ages every entry by one search.
+    pub fn new_search(&self) {
+        let generation = (self.generation() + 1) % GENERATIONS;
+        self.generation.store(generation, Ordering::Relaxed);
+    }
+
+    /// Looks up the search result for the position with Zobrist key `key`, `ply` plies from the root.
+    #[must_use]
+    pub fn probe(&self, key: u64, ply: usize) -> Option<TtEntry> {
+        let data = self.find(key)?;
+        Some(TtEntry {
+            mv: Move::from_raw((data >> 16) as u16),
+            score: score_from_tt((data >> 32) as i16 as i32, ply),
+            depth: depth_of(data) as i32,
+            bound: Bound::from_index(bound_of(data)),
+        })
+    }
+
+    /// Stores a search result. Without a move, the move of an earlier entry for the same position
+    /// is kept.
+    pub fn store(&self, key: u64, ply: usize, mv: Option<Move>, score: i32, depth: i32, bound: Bound) {
+        let mut raw = mv.map_or(0, Move::raw);
+        if raw == 0 {
+            raw = self.find(key).map_or(0, |data| (data >> 16) as u16);
+        }
+
+        let score = score_to_tt(score, ply) as i16 as u16;
+        let payload = (score as u64) << 16 | raw as u64;
+        self.write(key, depth.clamp(0, u8::MAX as i32) as u8, bound, payload);
+    }
+
+    /// Looks up a perft count for the position with Zobrist key `key`.
+    #[must_use]
+    pub fn probe_count(&self, key: u64, depth: i32) -> Option<u64> {
+        self.find(perft_key(key, depth)).map(|data| data >> 16)
+    }
+
+    /// Stores a perft count. Counts that do not fit in 48 bits are not stored.
+    pub fn store_count(&self, key: u64, depth: i32, count: u64) {
+        if count < 1 << 48 {
+            self.write(perft_key(key, depth), depth as u8, Bound::Exact, count);
+        }
+    }
+
+    /// How full the table is, in permille, judged by the entries written during the current search
+    /// among the first thousand slots.
+    #[must_use]
+    pub fn hashfull(&self) -> usize {
+        let generation = self.generation();
+        let slots = self
+            .buckets
+            .iter()
+            .take(1000 / BUCKET_SIZE)
+            .flat_map(|bucket| &bucket.0);
+        let (used, total) = slots.fold((0, 0), |(used, total), slot| {
+            let (_, data) = slot.load();
+            let current = data != 0 && generation_of(data) == generation;
+            (used + current as usize, total + 1)
+        });
+        used * 1000 / total
+    }
+
+    fn generation(&self) -> u8 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    fn bucket(&self, key: u64) -> &Bucket {
+        let index = (key as u128 * self.buckets.len() as u128) >> 64;
+        &self.buckets[index as usize]
+    }
+
+    fn find(&self, key: u64) -> Option<u64> {
+        self.bucket(key).0.iter().find_map(|slot| {
+            let (stored, data) = slot.load();
+            (data != 0 && stored == key).then_some(data)
+        })
+    }
+
+    fn write(&self, key: u64, depth: u8, bound: Bound, payload: u64) {
+        let generation = self.generation();
+        let data = payload << 16 | (generation as u64) << 10 | (bound as u64) << 8 | depth as u64;
+
+        let bucket = self.bucket(key);
+        let mut victim = &bucket.0[0];
+        let mut lowest = i32::MAX;
+        for slot in &bucket.0 {
+            let (stored, old) = slot.load();
+            if old == 0 {
+                victim = slot;
+                break;
+            }
+
+            if stored == key {
+                // Keep a deeper result for the same position unless it is left over from an
+                // earlier search or the new one is exact.
+                let fresh = generation_of(old) == generation;
+                if fresh && bound != Bound::Exact && depth_of(old) > depth.saturating_add(2) {
+                    return;
+                }
+                victim = slot;
+                break;
+            }
+
+            let age = (generation + GENERATIONS - generation_of(old)) % GENERATIONS;
+            let value = depth_of(old) as i32 - 8 * age as i32;
+            if value < lowest {
+                lowest = value;
+                victim = slot;
+            }
+        }
+
+        victim.store(key, data);
+    }
+}
+
+fn perft_key(key: u64, depth: i32) -> u64 {
+    key ^ PERFT_SALT.wrapping_mul(depth as u64 + 1)
+}
+
+/// Win scores count plies from the root. In the table they are stored relative to the node instead,
+/// so that they stay correct when the position is reached along a line of different length.
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= WIN_IN_MAX_PLY {
+        score + ply as i32
+    } else if score <= -WIN_IN_MAX_PLY {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= WIN_IN_MAX_PLY {
+        score - ply as i32
+    } else if score <= -WIN_IN_MAX_PLY {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::WIN;
+    use std::str::FromStr;
+
+    #[test]
+    fn store_and_probe() {
+        let tt = TranspositionTable::new(1);
+        let mv = Move::from_str("3c3>12").unwrap();
+        tt.store(0x1234, 3, Some(mv), -250, 7, Bound::Lower);
+
+        let entry = tt.probe(0x1234, 3).unwrap();
+        assert!(entry.mv == Some(mv));
+        assert_eq!(entry.score, -250);
+        assert_eq!(entry.depth, 7);
+        assert_eq!(entry.bound, Bound::Lower);
+        assert!(tt.probe(0x1235, 3).is_none());
+
+        tt.store(0x1234, 3, None, 100, 8, Bound::Upper);
+        let entry = tt.probe(0x1234, 3).unwrap();
+        assert!(entry.mv == Some(mv));
+        assert_eq!(entry.bound, Bound::Upper);
+
+        tt.clear();
+        assert!(tt.probe(0x1234, 3).is_none());
+    }
+
+    #[test]
+    fn win_scores() {
+        let tt = TranspositionTable::new(1);
+        tt.store(1, 4, None, WIN - 9, 5, Bound::Exact);
+        assert_eq!(tt.probe(1, 4).unwrap().score, WIN - 9);
+        assert_eq!(tt.probe(1, 2).unwrap().score, WIN - 7);
+
+        tt.store(2, 4, None, -WIN + 9, 5, Bound::Exact);
+        assert_eq!(tt.probe(2, 6).unwrap().score, -WIN + 11);
+    }
+
+    #[test]
+    fn replacement() {
+        let tt = TranspositionTable::new(0);
+        assert_eq!(tt.buckets.len(), 1);
+
+        for key in 1..=4 {
+            tt.store(key, 0, None, 0, 10 + key as i32, Bound::Exact);
+        }
+        tt.store(5, 0, None, 0, 1, Bound::Exact);
+        assert!(tt.probe(1, 0).is_none());
+        assert!(tt.probe(5, 0).is_some());
+
+        // A shallower result does not overwrite a deeper one for the same position...
+        tt.store(4, 0, None, 50, 3, Bound::Lower);
+        assert_eq!(tt.probe(4, 0).unwrap().depth, 14);
+
+        // ...unless the deeper one is from an earlier search.
+        tt.new_search();
+        tt.store(4, 0, None, 50, 3, Bound::Lower);
+        assert_eq!(tt.probe(4, 0).unwrap().depth, 3);
+
+        // Entries from earlier searches lose value.
+        tt.store(6, 0, None, 0, 20, Bound::Exact);
+        assert!(tt.probe(5, 0).is_none());
+        assert!(tt.probe(4, 0).is_some());
+    }
+
+    #[test]
+    fn perft_counts() {
+        let tt = TranspositionTable::new(1);
+        tt.store_count(42, 3, 1_234_567);
+        assert_eq!(tt.probe_count(42, 3), Some(1_234_567));
+        assert_eq!(tt.probe_count(42, 4), None);
+        assert!(tt.probe(42, 0).is_none());
+
+        tt.store_count(43, 3, 1 << 48);
+        assert_eq!(tt.probe_count(43, 3), None);
+    }
+
+    #[test]
+    fn hashfull() {
+        let tt = TranspositionTable::new(1);
+        assert_eq!(tt.hashfull(), 0);
+        for key in 0..100_000u64 {
+            tt.store(key.wrapping_mul(0x9E3779B97F4A7C15), 0, None, 0, 1, Bound::Exact);
+        }
+        assert!(tt.hashfull() > 900);
+        tt.new_search();
+        assert_eq!(tt.hashfull(), 0);
+    }
+}
@@ -0,0 +1,36 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    sync::Mutex,
+};
+
+static FILE: Mutex<Option<File>> = Mutex::new(None);
+
+/// Starts appending all protocol traffic to the file at `path`, or stops logging if `path` is
+/// empty.
+pub fn open(path: &str) -> io::Result<()> {
+    let file = match path {
+        "" => None,
+        path => Some(File::options().create(true).append(true).open(path)?),
+    };
+    *FILE.lock().unwrap() = file;
+    Ok(())
+}
+
+/// Records a line received from the GUI.
+pub fn input(line: &str) {
+    if let Some(file) = FILE.lock().unwrap().as_mut() {
+        let _ = writeln!(file, ">> {line}");
+    }
+}
+
+/// Prints a line for the GUI and records it. Use the `send!` macro rather than calling this
+/// directly.
+pub fn send(line: &str) {
+    // Holding the lock while printing keeps the log in the same order as the output.
+    let mut file = FILE.lock().unwrap();
+    println!("{line}");
+    if let Some(file) = file.as_mut() {
+        let _ = writeln!(file, "<< {line}");
+    }
+}
@@ -0,0 +1,526 @@
+use std::{
+    io,
+    ops::ControlFlow,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    bench,
+    game::Game,
+    perft::{self, Mode},
+    position::{EvalParams, Network, NetworkError, Position},
+    search::{self, Limits, MAX_PLY, Mcts, Searcher, TranspositionTable},
+};
+use options::Options;
+
+pub(crate) mod log;
+mod options;
+
+/// Prints a line for the GUI, copying it to the debug log if one is open.
+macro_rules! send {
+    ($($arg:tt)*) => {
+        $crate::tei::log::send(&format!($($arg)*))
+    };
+}
+pub(crate) use send;
+
+/// Long-running commands (`go`, `perft`, `tinue` and `bench`) run on a worker thread so that input
+/// keeps being read while they run. Only one of them may run at a time.
+pub struct Interface {
+    game: Game,
+    size: usize,
+    options: Options,
+    tt: Arc<TranspositionTable>,
+    /// The tree of the last MCTS search, kept so that the next one can carry on from it.
+    mcts: Arc<Mutex<Mcts>>,
+    /// The network that searches evaluate with when `UseNNUE` is set.
+    network: Arc<Network>,
+    stop: Arc<AtomicBool>,
+    /// Raised while a `go ponder` search waits for `ponderhit`.
+    ponder: Arc<AtomicBool>,
+    /// The running or last command on the worker thread, with its name.
+    worker: Option<(&'static str, JoinHandle<()>)>,
+}
+
+impl Default for Interface {
+    fn default() -> Interface {
+        let options = Options::default();
+        let hash = options.spin(options::HASH) as usize;
+        Interface {
+            game: Game::default(),
+            size: Position::DEFAULT_SIZE,
+            tt: Arc::new(TranspositionTable::new(hash)),
+            mcts: Arc::new(Mutex::new(Mcts::new(hash))),
+            network: Arc::new(Network::embedded()),
+            options,
+            stop: Arc::default(),
+            ponder: Arc::default(),
+            worker: None,
+        }
+    }
+}
+
+impl Drop for Interface {
+    fn drop(&mut self) {
+        self.stop_worker();
+    }
+}
+
+impl Interface {
+    /// An interface that evaluates with the network in the default `EvalFile`, if that file exists,
+    /// rather than the embedded one.
+    #[must_use]
+    pub fn new() -> Interface {
+        let mut interface = Interface::default();
+        interface.load_eval_file(false);
+        interface
+    }
+
+    /// Handles one line of input. Breaks once the engine should exit.
+    pub fn parse_line(&mut self, line: &str) -> ControlFlow<()> {
+        let start_time = Instant::now();
+        log::input(line.trim_end());
+
+        let mut it = line.split_ascii_whitespace();
+        let Some(cmd) = it.next() else {
+            return ControlFlow::Continue(());
+        };
+
+        match cmd {
+            "tei" => self.print_handshake(),
+            "teinewgame" => self.parse_teinewgame(it),
+            "isready" => send!("readyok"),
+            "stop" => self.stop_worker(),
+            "ponderhit" => self.ponderhit(),
+            "quit" => return ControlFlow::Break(()),
+            "position" => self.parse_position(it),
+            "moves" => self.parse_moves(it),
+            "go" => self.parse_go(it, start_time),
+            "perft" => self.parse_perft(it),
+            "tinue" => self.parse_tinue(it),
+            "bench" => self.parse_bench(it),
+            "undo" => self.undo_move(),
+            "redo" => self.redo_move(),
+            "setoption" => self.parse_setoption(it),
+            "d" => self.print_position(),
+            "eval" => self.print_eval(),
+            _ => self.print_protocol_error(cmd, "Unknown command"),
+        }
+
+        ControlFlow::Continue(())
+    }
+
+    fn print_handshake(&self) {
+        send!("id name {} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        send!("id author the {} authors", env!("CARGO_PKG_NAME"));
+        for option in self.options.iter() {
+            send!("{option}");
+        }
+        send!("teiok");
+    }
+
+    /// Handles `teinewgame [<size> [<half-komi>]]`: starts a new game on a board of the given size,
+    /// 6x6 if none is given. Later `position startpos` commands use the same size. A half-komi sets
+    /// the `HalfKomi` option as `setoption` would.
+    fn parse_teinewgame<'a, I: Iterator<Item = &'a str>>(&mut self, mut it: I) {
+        let size = match it.next().map(str::parse::<usize>) {
+            None => Position::DEFAULT_SIZE,
+            Some(Ok(size)) if Position::is_valid_size(size) => size,
+            Some(Ok(size)) => {
+                return self.print_protocol_error("teinewgame", &format!("unsupported board size {size}"));
+            }
+            Some(Err(err)) => {
+                return self.print_protocol_error("teinewgame", &format!("invalid size argument: {err}"));
+            }
+        };
+        let half_komi = it.next();
+        if let Some(token) = it.next() {
+            return self.print_unrecognised_token("teinewgame", token);
+        }
+
+        if let Some(half_komi) = half_komi {
+            match self.options.set(options::HALF_KOMI, Some(half_komi)) {
+                Ok(name) => self.apply_option(name),
+                Err(err) => return self.print_protocol_error("teinewgame", &err.to_string()),
+            }
+        }
+
+        self.size = size;
+        self.new_game();
+        self.tt.clear();
+        self.reset_mcts();
+    }
+
+    fn new_game(&mut self) {
+        self.game = Game::new(Position::startpos(self.size));
+        self.game.set_half_komi(self.half_komi());
+    }
+
+    /// Throws away the MCTS tree. A search still running keeps the tree it started with.
+    fn reset_mcts(&mut self) {
+        self.mcts = Arc::new(Mutex::new(Mcts::new(self.options.spin(options::HASH) as usize)));
+    }
+
+    fn half_komi(&self) -> u8 {
+        self.options.spin(options::HALF_KOMI) as u8
+    }
+
+    fn parse_position<'a, I: Iterator<Item = &'a str>>(&mut self, mut it: I) {
+        let Some(pos_type) = it.next() else {
+            return self.print_protocol_error("position", "Empty position");
+        };
+
+        match pos_type {
+            "startpos" => self.new_game(),
+            "tps" => {
+                let Ok([board, stm, fullmove]) = it.next_chunk::<3>() else {
+                    return self.print_protocol_error(
+                        "position",
+                        "incorrect number of whitespace-separated components in tps",
+                    );
+                };
+
+                match Position::parse_from_parts(board, stm, fullmove) {
+                    Ok(position) => {
+                        self.game = Game::new(position);
+                        self.game.set_half_komi(self.half_komi());
+                    }
+                    Err(err) => return self.print_protocol_error("position", &format!("cannot parse tps: {err}")),
+                }
+            }
+            _ => return self.print_unrecognised_token("position", pos_type),
+        }
+
+        match it.next() {
+            None => {}
+            Some("moves") => self.parse_moves(it),
+            Some(token) => self.print_unrecognised_token("position", token),
+        }
+    }
+
+    fn parse_moves<'a, I: Iterator<Item = &'a str>>(&mut self, it: I) {
+        // Moves are applied to a copy so that an illegal move leaves the game untouched.
+        let mut game = self.game.clone();
+        for mstr in it {
+            match game.play_str(mstr) {
+                Ok(_) => game.position().verify(),
+                Err(err) => return self.print_protocol_error("moves", &err.to_string()),
+            }
+        }
+        self.game = game;
+    }
+
+    fn undo_move(&mut self) {
+        if self.game.undo().is_none() {
+            self.print_protocol_error("undo", "no moves to take back");
+        }
+    }
+
+    fn redo_move(&mut self) {
+        if self.game.redo().is_none() {
+            self.print_protocol_error("redo", "no moves to replay");
+        }
+    }
+
+    /// Handles `setoption name <name> [value <value>]`. Both the name and the value may contain
+    /// spaces.
+    fn parse_setoption<'a, I: Iterator<Item = &'a str>>(&mut self, mut it: I) {
+        if it.next() != Some("name") {
+            return self.print_protocol_error("setoption", "expected `setoption name <name> [value <value>]`");
+        }
+
+        let mut name = Vec::new();
+        let mut value = None;
+        for token in it.by_ref() {
+            if token == "value" {
+                value = Some(it.collect::<Vec<_>>().join(" "));
+                break;
+            }
+            name.push(token);
+        }
+
+        match self.options.set(&name.join(" "), value.as_deref()) {
+            Ok(name) => self.apply_option(name),
+            Err(err) => self.print_protocol_error("setoption", &err.to_string()),
+        }
+    }
+
+    /// Passes a newly set option on to whatever depends on it. Options that are only read when
+    /// needed, such as the move overhead, need nothing here.
+    fn apply_option(&mut self, name: &str) {
+        match name {
+            // Komi changes the score of games decided on flats, and the table does not key on it.
+            options::HALF_KOMI => {
+                self.game.set_half_komi(self.half_komi());
+                self.tt.clear();
+                self.reset_mcts();
+            }
+            // A search still running keeps the table it started with.
+            options::HASH => {
+                self.tt = Arc::new(TranspositionTable::new(self.options.spin(options::HASH) as usize));
+                self.reset_mcts();
+            }
+            // Like komi, the evaluation changes scores that the table and the tree have stored.
+            options::USE_NNUE => {
+                self.tt.clear();
+                self.reset_mcts();
+            }
+            options::EVAL_FILE => self.load_eval_file(true),
+            options::CLEAR_HASH => {
+                self.tt.clear();
+                self.reset_mcts();
+            }
+            options::DEBUG_LOG_FILE => {
+                let path = self.options.string(options::DEBUG_LOG_FILE);
+                if let Err(err) = log::open(path) {
+                    self.print_protocol_error("setoption", &format!("cannot open `{path}`: {err}"));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Loads the network named by the `EvalFile` option, or the embedded one if the option is empty
+    /// or, unless `required`, the file does not exist. On failure the current network is kept.
+    fn load_eval_file(&mut self, required: bool) {
+        let path = self.options.string(options::EVAL_FILE);
+        let network = match path {
+            "" => Network::embedded(),
+            _ => match Network::load(path) {
+                Ok(network) => network,
+                Err(NetworkError::Io(err)) if !required && err.kind() == io::ErrorKind::NotFound => Network::embedded(),
+                Err(err) => {
+                    let msg = format!("cannot load network `{path}`: {err}");
+                    return self.print_protocol_error("setoption", &msg);
+                }
+            },
+        };
+        self.network = Arc::new(network);
+        if self.options.check(options::USE_NNUE) {
+            self.tt.clear();
+            self.reset_mcts();
+        }
+    }
+
+    fn parse_go<'a, I: Iterator<Item = &'a str>>(&mut self, mut it: I, start_time: Instant) {
+        let mut limits = Limits {
+            move_overhead: Duration::from_millis(self.options.spin(options::MOVE_OVERHEAD) as u64),
+            ..Limits::default()
+        };
+        let mut ponder = false;
+        while let Some(token) = it.next() {
+            match token {
+                "infinite" => limits.infinite = true,
+                "ponder" => ponder = true,
+                "depth" | "nodes" | "movetime" | "wtime" | "btime" | "winc" | "binc" | "movestogo" => {
+                    let value: u64 = match it.next().map(str::parse) {
+                        Some(Ok(value)) => value,
+                        Some(Err(err)) => {
+                            return self.print_protocol_error("go", &format!("invalid {token} argument: {err}"));
+                        }
+                        None => return self.print_protocol_error("go", &format!("missing {token} argument")),
+                    };
+                    match token {
+                        "depth" => limits.depth = Some(value.min(MAX_PLY as u64) as i32),
+                        "nodes" => limits.nodes = Some(value),
+                        "movetime" => limits.movetime = Some(Duration::from_millis(value)),
+                        "wtime" => limits.time[0] = Some(Duration::from_millis(value)),
+                        "btime" => limits.time[1] = Some(Duration::from_millis(value)),
+                        "winc" => limits.inc[0] = Duration::from_millis(value),
+                        "binc" => limits.inc[1] = Duration::from_millis(value),
+                        _ => limits.movestogo = Some(value.min(u32::MAX as u64) as u32),
+                    }
+                }
+                _ => return self.print_unrecognised_token("go", token),
+            }
+        }
+
+        if self.game.result().is_some() {
+            return self.print_protocol_error("go", "the game is already over");
+        }
+        if self.worker_running() {
+            return self.print_protocol_error("go", "another command is still running");
+        }
+
+        let pos = self.game.position().clone();
+        let tt = Arc::clone(&self.tt);
+        let threads = self.options.spin(options::THREADS) as usize;
+        let multipv = self.options.spin(options::MULTI_PV) as usize;
+        let mcts = (self.options.combo(options::SEARCH_MODE) == options::MCTS).then(|| Arc::clone(&self.mcts));
+        let network = self.options.check(options::USE_NNUE).then(|| Arc::clone(&self.network));
+        // Raised before the worker starts, so the search cannot begin on the clock.
+        self.ponder.store(ponder, Ordering::Relaxed);
+        let ponder_flag = Arc::clone(&self.ponder);
+        self.spawn_worker("go", move |stop| {
+            let infinite = limits.infinite;
+            let result = match mcts {
+                Some(mcts) => {
+                    let mut mcts = mcts.lock().unwrap_or_else(|err| {
+                        // A search that panicked may have left the tree half updated.
+                        mcts.clear_poison();
+                        let mut mcts = err.into_inner();
+                        mcts.clear();
+                        mcts
+                    });
+                    mcts.set_network(network);
+                    mcts.search(&pos, &limits, start_time, &stop, &ponder_flag).unwrap()
+                }
+                None => {
+                    let mut searcher = Searcher::new(limits, start_time, Arc::clone(&stop), tt);
+                    searcher.set_threads(threads);
+                    searcher.set_multipv(multipv);
+                    searcher.set_ponder(Arc::clone(&ponder_flag));
+                    searcher.set_network(network);
+                    searcher.search(&pos).unwrap()
+                }
+            };
+
+            // An infinite or pondering search only reports its move once it has been stopped, or
+            // for pondering, once the opponent has played the expected move.
+            while (infinite || ponder_flag.load(Ordering::Relaxed)) && !stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            ponder_flag.store(false, Ordering::Relaxed);
+            match result.pv().get(1) {
+                Some(reply) => send!("bestmove {} ponder {reply}", result.best_move),
+                None => send!("bestmove {}", result.best_move),
+            }
+        });
+    }
+
+    /// Handles `ponderhit`: the opponent played the move the ponder search assumed, so it goes on as
+    /// a normal search on the clock it was given.
+    fn ponderhit(&mut self) {
+        if !self.ponder.swap(false, Ordering::Relaxed) {
+            self.print_protocol_error("ponderhit", "not pondering");
+        }
+    }
+
+    fn parse_perft<'a, I: Iterator<Item = &'a str>>(&mut self, mut it: I) {
+        let depth = it.next().unwrap_or("1");
+        let depth = match depth.parse() {
+            Ok(depth) => depth,
+            Err(err) => return self.print_protocol_error("perft", &format!("invalid depth argument: {err}")),
+        };
+
+        let mode = match it.next() {
+            None | Some("copy") => Mode::CopyMake,
+            Some("unmake") => Mode::Unmake,
+            Some("hash") => Mode::Hashed,
+            Some(token) => return self.print_unrecognised_token("perft", token),
+        };
+
+        let pos = self.game.position().clone();
+        let tt = Arc::clone(&self.tt);
+        self.spawn_worker("perft", move |stop| perft::splitperft(&pos, depth, mode, &stop, &tt));
+    }
+
+    /// Handles `tinue [maxdepth]`, which looks for a forced road win within `maxdepth` plies.
+    fn parse_tinue<'a, I: Iterator<Item = &'a str>>(&mut self, mut it: I) {
+        let max_depth = match it.next().map(str::parse::<usize>) {
+            None => 9,
+            Some(Ok(depth)) => depth.min(MAX_PLY),
+            Some(Err(err)) => return self.print_protocol_error("tinue", &format!("invalid depth argument: {err}")),
+        };
+        if self.game.result().is_some() {
+            return self.print_protocol_error("tinue", "the game is already over");
+        }
+
+        let pos = self.game.position().clone();
+        self.spawn_worker("tinue", move |stop| match search::solve_tinue(&pos, max_depth, &stop) {
+            Some(line) => {
+                let line: Vec<String> = line.iter().map(|mv| mv.to_string()).collect();
+                send!("tinue in {} plies: {}", line.len(), line.join(" "));
+            }
+            None if stop.load(Ordering::Relaxed) => send!("tinue: stopped"),
+            None => send!("no tinue within {max_depth} plies"),
+        });
+    }
+
+    /// Handles `bench [depth] [threads]`, which compares the time to `depth` with one thread and
+    /// with `threads`, by default as many as the machine has.
+    fn parse_bench<'a, I: Iterator<Item = &'a str>>(&mut self, mut it: I) {
+        let depth = match it.next().map(str::parse::<i32>) {
+            None => 5,
+            Some(Ok(depth)) => depth.clamp(1, MAX_PLY as i32 - 1),
+            Some(Err(err)) => return self.print_protocol_error("bench", &format!("invalid depth argument: {err}")),
+        };
+        let threads = match it.next().map(str::parse::<usize>) {
+            None => thread::available_parallelism().map_or(1, usize::from),
+            Some(Ok(threads)) => threads.max(1),
+            Some(Err(err)) => return self.print_protocol_error("bench", &format!("invalid threads argument: {err}")),
+        };
+
+        let hash = self.options.spin(options::HASH) as usize;
+        self.spawn_worker("bench", move |stop| bench::bench(depth, threads, hash, &stop));
+    }
+
+    fn spawn_worker<F: FnOnce(Arc<AtomicBool>) + Send + 'static>(&mut self, cmd: &'static str, f: F) {
+        if self.worker_running() {
+            return self.print_protocol_error(cmd, "another command is still running");
+        }
+        // Reports the previous command if it panicked.
+        self.stop_worker();
+
+        self.stop.store(false, Ordering::Relaxed);
+        let stop = Arc::clone(&self.stop);
+        self.worker = Some((cmd, thread::spawn(move || f(stop))));
+    }
+
+    fn worker_running(&self) -> bool {
+        self.worker.as_ref().is_some_and(|(_, worker)| !worker.is_finished())
+    }
+
+    /// Stops the running command, if any, and waits for it to print its result. A command that
+    /// panicked is reported as an error, and the engine keeps reading input.
+    fn stop_worker(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some((cmd, worker)) = self.worker.take()
+            && let Err(panic) = worker.join()
+        {
+            let msg = panic
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown panic");
+            self.print_protocol_error(cmd, &format!("command panicked: {msg}"));
+        }
+    }
+
+    fn print_position(&self) {
+        let position = self.game.position();
+        let moves: Vec<String> = self.game.moves().map(|mv| mv.to_string()).collect();
+        send!("start: {}", self.game.start());
+        send!("moves: {}", moves.join(" "));
+        send!("tps: {}", position);
+        send!("key: {:016x}", position.key());
+        let (canonical, sym) = position.canonical();
+        send!("canonical: {:016x} ({sym:?})", canonical.key());
+        send!("repetitions: {}", self.game.repetitions());
+        if let Some(result) = self.game.result() {
+            send!("result: {result}");
+        }
+    }
+
+    fn print_eval(&self) {
+        let breakdown = self.game.position().eval_breakdown(&EvalParams::DEFAULT);
+        for line in breakdown.to_string().lines() {
+            send!("{line}");
+        }
+
+        send!("nnue: {}", self.network.evaluate(self.game.position()));
+    }
+
+    fn print_protocol_error(&self, cmd: &str, msg: &str) {
+        send!("info error ({cmd}): {msg}");
+    }
+
+    fn print_unrecognised_token(&self, cmd: &str, token: &str) {
+        self.print_protocol_error(cmd, &format!("unrecognised token `{}`", token));
+    }
+}
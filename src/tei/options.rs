@@ -0,0 +1,336 @@
+use crate::position::Position;
+use std::fmt;
+use thiserror::Error;
+
+pub const HASH: &str = "Hash";
+pub const THREADS: &str = "Threads";
+pub const MULTI_PV: &str = "MultiPV";
+pub const SEARCH_MODE: &str = "SearchMode";
+pub const USE_NNUE: &str = "UseNNUE";
+pub const EVAL_FILE: &str = "EvalFile";
+pub const HALF_KOMI: &str = "HalfKomi";
+pub const MOVE_OVERHEAD: &str = "MoveOverhead";
+pub const PONDER: &str = "Ponder";
+pub const DEBUG_LOG_FILE: &str = "DebugLogFile";
+pub const CLEAR_HASH: &str = "Clear Hash";
+
+/// Values of the `SearchMode` option.
+pub const ALPHA_BETA: &str = "AlphaBeta";
+pub const MCTS: &str = "MCTS";
+
+/// The type of an engine option together with its default and allowed values.
+#[derive(Clone, Debug)]
+pub enum OptionKind {
+    Spin {
+        default: i64,
+        min: i64,
+        max: i64,
+    },
+    Check {
+        default: bool,
+    },
+    Combo {
+        default: &'static str,
+        choices: &'static [&'static str],
+    },
+    String {
+        default: &'static str,
+    },
+    Button,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum OptionValue {
+    Spin(i64),
+    Check(bool),
+    Combo(&'static str),
+    String(String),
+    Button,
+}
+
+#[derive(Debug, Error)]
+pub enum OptionError {
+    #[error("unknown option `{0}`")]
+    Unknown(String),
+    #[error("missing value for option {0}")]
+    MissingValue(&'static str),
+    #[error("invalid value `{value}` for option {name}")]
+    Invalid { name: &'static str, value: String },
+    #[error("{name} must be between {min} and {max}")]
+    OutOfRange { name: &'static str, min: i64, max: i64 },
+}
+
+pub struct EngineOption {
+    name: &'static str,
+    kind: OptionKind,
+    value: OptionValue,
+}
+
+impl EngineOption {
+    #[must_use]
+    pub fn new(name: &'static str, kind: OptionKind) -> EngineOption {
+        let value = match kind {
+            OptionKind::Spin { default, .. } => OptionValue::Spin(default),
+            OptionKind::Check { default } => OptionValue::Check(default),
+            OptionKind::Combo { default, .. } => OptionValue::Combo(default),
+            OptionKind::String { default } => OptionValue::String(default.to_string()),
+            OptionKind::Button => OptionValue::Button,
+        };
+        EngineOption { name, kind, value }
+    }
+
+    #[must_use]
+    pub fn value(&self) -> &OptionValue {
+        &self.value
+    }
+
+    fn parse(&self, value: Option<&str>) -> Result<OptionValue, OptionError> {
+        let invalid = |value: &str| OptionError::Invalid {
+            name: self.name,
+            value: value.to_string(),
+        };
+
+        match (&self.kind, value) {
+            (OptionKind::Button, _) => return Ok(OptionValue::Button),
+            (OptionKind::String { .. }, None | Some("<empty>")) => return Ok(OptionValue::String(String::new())),
+            _ => {}
+        }
+        let value = value.ok_or(OptionError::MissingValue(self.name))?;
+
+        match self.kind {
+            OptionKind::Spin { min, max, .. } => {
+                let n: i64 = value.parse().map_err(|_| invalid(value))?;
+                if !(min..=max).contains(&n) {
+                    return Err(OptionError::OutOfRange {
+                        name: self.name,
+                        min,
+                        max,
+                    });
+                }
+                Ok(OptionValue::Spin(n))
+            }
+            OptionKind::Check { .. } => match value {
+                "true" => Ok(OptionValue::Check(true)),
+                "false" => Ok(OptionValue::Check(false)),
+                _ => Err(invalid(value)),
+            },
+            OptionKind::Combo { choices, .. } => choices
+                .iter()
+                .find(|choice| choice.eq_ignore_ascii_case(value))
+                .map(|&choice| OptionValue::Combo(choice))
+                .ok_or_else(|| invalid(value)),
+            OptionKind::String { .. } => Ok(OptionValue::String(value.to_string())),
+            OptionKind::Button => unreachable!("buttons have no value"),
+        }
+    }
+}
+
+/// Formats the option as its `option` line in the `tei` handshake.
+impl fmt::Display for EngineOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "option name {} type ", self.name)?;
+        match &self.kind {
+            OptionKind::Spin { default, min, max } => write!(f, "spin default {default} min {min} max {max}"),
+            OptionKind::Check { default } => write!(f, "check default {default}"),
+            OptionKind::Combo { default, choices } => {
+                write!(f, "combo default {default}")?;
+                choices.iter().try_for_each(|choice| write!(f, " var {choice}"))
+            }
+            OptionKind::String { default: "" } => write!(f, "string default <empty>"),
+            OptionKind::String { default } => write!(f, "string default {default}"),
+            OptionKind::Button => write!(f, "button"),
+        }
+    }
+}
+
+/// Every option the engine understands. Values are looked up by name, which must be one of the
+/// constants in this module. Asking for a value as the wrong type is a bug and panics.
+pub struct Options {
+    options: Vec<EngineOption>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        let spin = |default, min, max| OptionKind::Spin { default, min, max };
+        Options {
+            options: vec![
+                EngineOption::new(HASH, spin(16, 1, 65536)),
+                EngineOption::new(THREADS, spin(1, 1, 256)),
+                EngineOption::new(MULTI_PV, spin(1, 1, 256)),
+                // MCTS searches on one thread with a single line, so it ignores Threads and MultiPV.
+                EngineOption::new(
+                    SEARCH_MODE,
+                    OptionKind::Combo {
+                        default: ALPHA_BETA,
+                        choices: &[ALPHA_BETA, MCTS],
+                    },
+                ),
+                EngineOption::new(USE_NNUE, OptionKind::Check { default: false }),
+                // Read at startup if it exists. Empty means the network built into the engine.
+                EngineOption::new(
+                    EVAL_FILE,
+                    OptionKind::String {
+                        default: "pentakle.nnue",
+                    },
+                ),
+                EngineOption::new(HALF_KOMI, spin(Position::DEFAULT_HALF_KOMI.into(), 0, 20)),
+                EngineOption::new(MOVE_OVERHEAD, spin(30, 0, 5000)),
+                // Only tells the GUI that the engine can ponder; `go ponder` works either way.
+                EngineOption::new(PONDER, OptionKind::Check { default: false }),
+                EngineOption::new(DEBUG_LOG_FILE, OptionKind::String { default: "" }),
+                EngineOption::new(CLEAR_HASH, OptionKind::Button),
+            ],
+        }
+    }
+}
+
+impl Options {
+    pub fn iter(&self) -> impl Iterator<Item = &EngineOption> {
+        self.options.iter()
+    }
+
+    /// Sets the option called `name`, ignoring case, and returns its canonical name. Buttons take
+    /// no value; the caller is expected to act on them.
+    pub fn set(&mut self, name: &str, value: Option<&str>) -> Result<&'static str, OptionError> {
+        let option = self
+            .options
+            .iter_mut()
+            .find(|option| option.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| OptionError::Unknown(name.to_string()))?;
+
+        option.value = option.parse(value)?;
+        Ok(option.name)
+    }
+
+    #[must_use]
+    pub fn spin(&self, name: &str) -> i64 {
+        match self.get(name) {
+            OptionValue::Spin(value) => *value,
+            value => panic!("option {name} is not a spin: {value:?}"),
+        }
+    }
+
+    #[must_use]
+    pub fn check(&self, name: &str) -> bool {
+        match self.get(name) {
+            OptionValue::Check(value) => *value,
+            value => panic!("option {name} is not a check: {value:?}"),
+        }
+    }
+
+    #[must_use]
+    pub fn combo(&self, name: &str) -> &'static str {
+        match self.get(name) {
+            OptionValue::Combo(value) => value,
+            value => panic!("option {name} is not a combo: {value:?}"),
+        }
+    }
+
+    #[must_use]
+    pub fn string(&self, name: &str) -> &str {
+        match self.get(name) {
+            OptionValue::String(value) => value,
+            value => panic!("option {name} is not a string: {value:?}"),
+        }
+    }
+
+    fn get(&self, name: &str) -> &OptionValue {
+        self.options
+            .iter()
+            .find(|option| option.name == name)
+            .map(EngineOption::value)
+            .unwrap_or_else(|| panic!("no option called {name}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> Options {
+        let mut options = Options::default();
+        options.options.push(EngineOption::new(
+            "Style",
+            OptionKind::Combo {
+                default: "Normal",
+                choices: &["Solid", "Normal", "Risky"],
+            },
+        ));
+        options
+    }
+
+    #[test]
+    fn defaults() {
+        let options = options();
+        assert_eq!(options.spin(HASH), 16);
+        assert_eq!(options.spin(HALF_KOMI), 4);
+        assert_eq!(options.string(DEBUG_LOG_FILE), "");
+        assert!(!options.check(PONDER));
+        assert_eq!(options.combo(SEARCH_MODE), ALPHA_BETA);
+        assert!(!options.check(USE_NNUE));
+        assert_eq!(options.string(EVAL_FILE), "pentakle.nnue");
+        assert_eq!(options.combo("Style"), "Normal");
+    }
+
+    #[test]
+    fn set() {
+        let mut options = options();
+        assert_eq!(options.set("halfkomi", Some("0")).unwrap(), HALF_KOMI);
+        assert_eq!(options.spin(HALF_KOMI), 0);
+        options.set("ponder", Some("true")).unwrap();
+        assert!(options.check(PONDER));
+        options.set("Style", Some("risky")).unwrap();
+        assert_eq!(options.combo("Style"), "Risky");
+        options.set(DEBUG_LOG_FILE, Some("/tmp/pentakle log.txt")).unwrap();
+        assert_eq!(options.string(DEBUG_LOG_FILE), "/tmp/pentakle log.txt");
+        options.set(DEBUG_LOG_FILE, Some("<empty>")).unwrap();
+        assert_eq!(options.string(DEBUG_LOG_FILE), "");
+        assert_eq!(options.set("clear hash", None).unwrap(), CLEAR_HASH);
+    }
+
+    #[test]
+    fn errors() {
+        let mut options = options();
+        assert!(matches!(
+            options.set("Contempt", Some("1")),
+            Err(OptionError::Unknown(_))
+        ));
+        assert!(matches!(options.set(HASH, None), Err(OptionError::MissingValue(HASH))));
+        assert!(matches!(
+            options.set(HASH, Some("lots")),
+            Err(OptionError::Invalid { .. })
+        ));
+        assert!(matches!(
+            options.set(HASH, Some("0")),
+            Err(OptionError::OutOfRange { .. })
+        ));
+        assert!(matches!(
+            options.set(PONDER, Some("yes")),
+            Err(OptionError::Invalid { .. })
+        ));
+        assert!(matches!(
+            options.set("Style", Some("Wild")),
+            Err(OptionError::Invalid { .. })
+        ));
+        assert_eq!(options.spin(HASH), 16);
+    }
+
+    #[test]
+    fn handshake() {
+        let lines: Vec<String> = options().iter().map(|option| option.to_string()).collect();
+        assert_eq!(lines[0], "option name Hash type spin default 16 min 1 max 65536");
+        assert_eq!(lines[2], "option name MultiPV type spin default 1 min 1 max 256");
+        assert_eq!(
+            lines[3],
+            "option name SearchMode type combo default AlphaBeta var AlphaBeta var MCTS"
+        );
+        assert_eq!(lines[5], "option name EvalFile type string default pentakle.nnue");
+        assert_eq!(lines[8], "option name Ponder type check default false");
+        assert_eq!(lines[9], "option name DebugLogFile type string default <empty>");
+        assert_eq!(lines[10], "option name Clear Hash type button");
+        assert_eq!(
+            lines[11],
+            "option name Style type combo default Normal var Solid var Normal var Risky"
+        );
+    }
+}
@@ -5,14 +5,28 @@ use crate::types::Square;
 pub struct Bitboard(pub u64);
 
 impl Bitboard {
-    pub const MASK: Self = Self(0xFFFFFFFFF);
+    pub const MASK: Self = Self(0xFFFFFFFFFFFFFFFF);
+
+    /// Squares that lie on a `size`x`size` board. Boards always use an 8-wide layout, so
+    /// smaller boards occupy the lower-left corner of the bitboard.
+    pub const fn board_mask(size: usize) -> Bitboard {
+        debug_assert!(size <= Square::STRIDE);
+        let rank = (1u64 << size) - 1;
+        let mut mask = 0;
+        let mut i = 0;
+        while i < size {
+            mask |= rank << (i * Square::STRIDE);
+            i += 1;
+        }
+        Bitboard(mask)
+    }
 
     pub const fn rank_mask(rank: usize) -> Bitboard {
-        Bitboard(0b000000_000000_000000_000000_000000_111111 << (rank * 6))
+        Bitboard(0x00000000000000FF << (rank * Square::STRIDE))
     }
 
     pub const fn file_mask(file: usize) -> Bitboard {
-        Bitboard(0b000001_000001_000001_000001_000001_000001 << file)
+        Bitboard(0x0101010101010101 << file)
     }
 
     pub fn set(&mut self, sq: Square) {
@@ -43,6 +57,15 @@ impl Bitboard {
         self.0.count_ones()
     }
 
+    /// Squares orthogonally adjacent to any square in `self`. Steps never wrap around the 8-wide
+    /// layout, but may leave a smaller board, so callers mask with the board where it matters.
+    #[must_use]
+    pub fn adjacent(self) -> Bitboard {
+        const NOT_H_FILE: u64 = !Bitboard::file_mask(7).0;
+        const NOT_A_FILE: u64 = !Bitboard::file_mask(0).0;
+        Bitboard(((self.0 & NOT_H_FILE) << 1) | ((self.0 & NOT_A_FILE) >> 1) | (self.0 << 8) | (self.0 >> 8))
+    }
+
     #[must_use]
     pub fn lsb(self) -> Square {
         if self.is_empty() {
@@ -3,9 +3,11 @@ mod color;
 mod r#move;
 mod piece;
 mod square;
+mod symmetry;
 
 pub use bitboard::*;
 pub use color::*;
 pub use r#move::*;
 pub use piece::*;
 pub use square::*;
+pub use symmetry::*;
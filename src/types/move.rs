@@ -36,13 +36,15 @@ impl std::fmt::Display for Dir {
     }
 }
 
+/// A move packed into 16 bits: the square in bits 0-5, the direction (spreads) or piece type plus
+/// one (placements) in bits 6-7, and the splat in bits 8-15. Placements have a zero splat.
 #[derive(Copy, Clone, Eq, Hash, PartialEq)]
 #[repr(transparent)]
 pub struct Move(NonZeroU16);
 
 impl Move {
-    const SPLAT_SHIFT: usize = 6;
-    const FLAGS_SHIFT: usize = 12;
+    const FLAGS_SHIFT: usize = 6;
+    const SPLAT_SHIFT: usize = 8;
 
     #[must_use]
     pub fn place(pt: PieceType, sq: Square) -> Move {
@@ -55,14 +57,20 @@ impl Move {
     #[must_use]
     pub fn spread(sq: Square, dir: Dir, splat: u8) -> Move {
         debug_assert!(splat != 0);
-        debug_assert!((splat & 0x3F) == splat);
         let dir = dir as u16;
         let splat = splat as u16;
         let sq = sq.to_index() as u16;
-        let raw = (dir << Self::FLAGS_SHIFT) | (splat << Self::SPLAT_SHIFT) | sq;
+        let raw = (splat << Self::SPLAT_SHIFT) | (dir << Self::FLAGS_SHIFT) | sq;
         Move(NonZeroU16::new(raw).unwrap())
     }
 
+    /// Rebuilds a move from `raw()`. Zero, used for "no move" in packed storage, gives `None`.
+    /// The result is not checked for legality in any position.
+    #[must_use]
+    pub fn from_raw(raw: u16) -> Option<Move> {
+        NonZeroU16::new(raw).map(Move)
+    }
+
     #[must_use]
     pub fn raw(self) -> u16 {
         self.0.get()
@@ -70,12 +78,12 @@ impl Move {
 
     #[must_use]
     pub fn splat(self) -> u8 {
-        (self.raw() >> Self::SPLAT_SHIFT & 0x3F) as u8
+        (self.raw() >> Self::SPLAT_SHIFT) as u8
     }
 
     #[must_use]
     pub fn flags(self) -> u16 {
-        self.raw() >> Self::FLAGS_SHIFT
+        (self.raw() >> Self::FLAGS_SHIFT) & 0x3
     }
 
     #[must_use]
@@ -143,7 +151,7 @@ impl FromStr for Move {
             }
             Some(&c) if c.is_ascii_digit() => {
                 it.next();
-                if ('1'..='6').contains(&c) {
+                if ('1'..='8').contains(&c) {
                     (PieceType::None, Some(c as usize - '0' as usize))
                 } else {
                     return Err(MoveParseError::InvalidLiftCount);
@@ -180,7 +188,7 @@ impl FromStr for Move {
         }
 
         for ch in it {
-            if !('1'..='6').contains(&ch) {
+            if !('1'..='8').contains(&ch) {
                 return Err(MoveParseError::InvalidTrailingCharacter);
             }
 
@@ -211,7 +219,7 @@ impl std::fmt::Display for Move {
                 PieceType::None => unreachable!(),
             }
         } else {
-            let mut splat = self.splat();
+            let mut splat = self.splat() as u32;
             let size = splat.bit_width();
 
             if size == 1 {
@@ -238,7 +246,22 @@ mod tests {
 
     #[test]
     fn roundtrip_moves() {
-        let cases = ["a1", "Cb4", "Sd3", "a1>", "d1-", "4c3>", "3b2+111", "5e4<23", "5b4>212"];
+        let cases = [
+            "a1",
+            "Cb4",
+            "Sd3",
+            "a1>",
+            "d1-",
+            "4c3>",
+            "3b2+111",
+            "5e4<23",
+            "5b4>212",
+            "h8",
+            "Sg7",
+            "8a1+11111111",
+            "8h1<",
+            "7g7-1123",
+        ];
 
         for case in cases {
             let mv = Move::from_str(case).unwrap();
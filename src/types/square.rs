@@ -7,18 +7,21 @@ use thiserror::Error;
 #[rustfmt::skip]
 #[allow(dead_code)]
 pub enum Square {
-    A1, B1, C1, D1, E1, F1,
-    A2, B2, C2, D2, E2, F2,
-    A3, B3, C3, D3, E3, F3,
-    A4, B4, C4, D4, E4, F4,
-    A5, B5, C5, D5, E5, F5,
-    A6, B6, C6, D6, E6, F6,
+    A1, B1, C1, D1, E1, F1, G1, H1,
+    A2, B2, C2, D2, E2, F2, G2, H2,
+    A3, B3, C3, D3, E3, F3, G3, H3,
+    A4, B4, C4, D4, E4, F4, G4, H4,
+    A5, B5, C5, D5, E5, F5, G5, H5,
+    A6, B6, C6, D6, E6, F6, G6, H6,
+    A7, B7, C7, D7, E7, F7, G7, H7,
+    A8, B8, C8, D8, E8, F8, G8, H8,
     #[default]
     None,
 }
 
 impl Square {
-    pub const NUM: usize = 36;
+    pub const NUM: usize = 64;
+    pub const STRIDE: usize = 8;
 
     #[must_use]
     pub const fn new(value: u8) -> Square {
@@ -29,10 +32,10 @@ impl Square {
 
     #[must_use]
     pub const fn from_file_and_rank(file: usize, rank: usize) -> Square {
-        debug_assert!(file < 6);
-        debug_assert!(rank < 6);
+        debug_assert!(file < Self::STRIDE);
+        debug_assert!(rank < Self::STRIDE);
 
-        let sq = file + rank * 6;
+        let sq = file + rank * Self::STRIDE;
         Square::new(sq as u8)
     }
 
@@ -43,12 +46,12 @@ impl Square {
 
     #[must_use]
     pub const fn file(self) -> usize {
-        self.to_index() % 6
+        self.to_index() % Self::STRIDE
     }
 
     #[must_use]
     pub const fn rank(self) -> usize {
-        self.to_index() / 6
+        self.to_index() / Self::STRIDE
     }
 
     #[must_use]
@@ -101,12 +104,12 @@ impl FromStr for Square {
             return Err(SquareParseError::IncorrectLength);
         };
 
-        let file = if (b'a'..=b'f').contains(file) {
+        let file = if (b'a'..=b'h').contains(file) {
             file - b'a'
         } else {
             return Err(SquareParseError::InvalidFile);
         };
-        let rank = if (b'1'..=b'6').contains(rank) {
+        let rank = if (b'1'..=b'8').contains(rank) {
             rank - b'1'
         } else {
             return Err(SquareParseError::InvalidRank);
@@ -0,0 +1,153 @@
+use crate::types::{Dir, Move, Square};
+
+/// One of the eight symmetries of the square board. Rotations are clockwise.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Symmetry {
+    Identity,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    FlipFiles,
+    FlipRanks,
+    Transpose,
+    AntiTranspose,
+}
+
+impl Symmetry {
+    pub const NUM: usize = 8;
+    pub const ALL: [Symmetry; Self::NUM] = [
+        Symmetry::Identity,
+        Symmetry::Rotate90,
+        Symmetry::Rotate180,
+        Symmetry::Rotate270,
+        Symmetry::FlipFiles,
+        Symmetry::FlipRanks,
+        Symmetry::Transpose,
+        Symmetry::AntiTranspose,
+    ];
+
+    #[allow(dead_code)]
+    #[must_use]
+    pub fn inverse(self) -> Symmetry {
+        match self {
+            Symmetry::Rotate90 => Symmetry::Rotate270,
+            Symmetry::Rotate270 => Symmetry::Rotate90,
+            _ => self,
+        }
+    }
+}
+
+impl Square {
+    #[must_use]
+    pub fn transform(self, sym: Symmetry, size: usize) -> Square {
+        let (f, r) = self.to_file_and_rank();
+        let n = size - 1;
+        let (file, rank) = match sym {
+            Symmetry::Identity => (f, r),
+            Symmetry::Rotate90 => (r, n - f),
+            Symmetry::Rotate180 => (n - f, n - r),
+            Symmetry::Rotate270 => (n - r, f),
+            Symmetry::FlipFiles => (n - f, r),
+            Symmetry::FlipRanks => (f, n - r),
+            Symmetry::Transpose => (r, f),
+            Symmetry::AntiTranspose => (n - r, n - f),
+        };
+        Square::from_file_and_rank(file, rank)
+    }
+}
+
+impl Dir {
+    #[allow(dead_code)]
+    #[must_use]
+    pub fn transform(self, sym: Symmetry) -> Dir {
+        let index = self as usize;
+        let index = match sym {
+            Symmetry::Identity => index,
+            Symmetry::Rotate90 => index + 1,
+            Symmetry::Rotate180 => index + 2,
+            Symmetry::Rotate270 => index + 3,
+            Symmetry::FlipFiles => [0, 3, 2, 1][index],
+            Symmetry::FlipRanks => [2, 1, 0, 3][index],
+            Symmetry::Transpose => [1, 0, 3, 2][index],
+            Symmetry::AntiTranspose => [3, 2, 1, 0][index],
+        };
+        Dir::from_index(index % Dir::NUM)
+    }
+}
+
+impl Move {
+    /// Applies `sym` to this move on a `size`x`size` board.
+    #[allow(dead_code)]
+    #[must_use]
+    pub fn transform(self, sym: Symmetry, size: usize) -> Move {
+        let sq = self.sq().transform(sym, size);
+        if self.is_place() {
+            Move::place(self.piece_type(), sq)
+        } else {
+            Move::spread(sq, self.dir().transform(sym), self.splat())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn squares() {
+        let cases = [
+            (Symmetry::Identity, "b1"),
+            (Symmetry::Rotate90, "a4"),
+            (Symmetry::Rotate180, "d5"),
+            (Symmetry::Rotate270, "e2"),
+            (Symmetry::FlipFiles, "d1"),
+            (Symmetry::FlipRanks, "b5"),
+            (Symmetry::Transpose, "a2"),
+            (Symmetry::AntiTranspose, "e4"),
+        ];
+        for (sym, expected) in cases {
+            let sq = Square::from_str("b1").unwrap().transform(sym, 5);
+            assert_eq!(sq.to_string(), expected, "{sym:?}");
+        }
+    }
+
+    #[test]
+    fn moves_follow_squares() {
+        for sym in Symmetry::ALL {
+            for size in 3..=8 {
+                for i in 0..size {
+                    let from = Square::from_file_and_rank(i, size / 2);
+                    for d in 0..Dir::NUM {
+                        let dir = Dir::from_index(d);
+                        let (f, r) = from.to_file_and_rank();
+                        let in_board = match dir {
+                            Dir::North => r + 1 < size,
+                            Dir::East => f + 1 < size,
+                            Dir::South => r > 0,
+                            Dir::West => f > 0,
+                        };
+                        if !in_board {
+                            continue;
+                        }
+                        let to = from.step(dir);
+                        let mv = Move::spread(from, dir, 1).transform(sym, size);
+                        assert_eq!(mv.sq().step(mv.dir()), to.transform(sym, size), "{sym:?}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn inverse() {
+        for sym in Symmetry::ALL {
+            for i in 0..36 {
+                let sq = Square::from_file_and_rank(i % 6, i / 6);
+                assert_eq!(sq.transform(sym, 6).transform(sym.inverse(), 6), sq);
+            }
+            let mv = Move::from_str("5c3>212").unwrap();
+            assert!(mv.transform(sym, 6).transform(sym.inverse(), 6) == mv);
+        }
+    }
+}